@@ -0,0 +1,147 @@
+use std::{
+    error::Error,
+    io::{Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+pub type BackendResult<T> = Result<T, Box<dyn Error>>;
+
+/// Captured result of a remote command
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32,
+}
+
+/// Protocol independent subset of file metadata
+#[derive(Debug, Clone, Default)]
+pub struct FileStat {
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    /// Unix mode bits, including the file type bits when the protocol reports them
+    pub perm: Option<u32>,
+    /// Modification time in seconds since the Unix epoch
+    pub mtime: Option<u64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+pub struct DirEntry {
+    pub path: PathBuf,
+    pub stat: FileStat,
+}
+
+/// Writer returned by `open_write`. Many protocols only store the data once the writer
+/// is done, so nothing counts as written before `finish` succeeded.
+pub trait RemoteWrite: Write {
+    /// Completes the write and reports whether the data landed
+    fn finish(self: Box<Self>) -> BackendResult<()>;
+}
+
+impl RemoteWrite for std::fs::File {
+    fn finish(self: Box<Self>) -> BackendResult<()> {
+        self.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Everything the REPL needs from a remote protocol.
+///
+/// Paths handed to the backend are already resolved against `cwd()`.
+pub trait RemoteBackend {
+    /// Label shown in the REPL prompt (e.g. `user@host:port`)
+    fn label(&self) -> String;
+
+    fn cwd(&self) -> &Path;
+
+    fn set_cwd(&mut self, path: PathBuf);
+
+    /// Whether `exec_stream` can run shell commands. Backends without a shell
+    /// get the REPL's built-in fallbacks for listing, `cd` and `cat` instead.
+    fn can_exec(&self) -> bool {
+        true
+    }
+
+    /// Whether `exec_stream` carries binary data unmodified, which tar transfers need.
+    /// Terminal based backends mangle control bytes and copy file by file instead.
+    fn can_stream(&self) -> bool {
+        self.can_exec()
+    }
+
+    /// Whether `!` commands go verbatim to an interactive CLI (e.g. a switch) rather
+    /// than to a shell in the current directory
+    fn passthrough(&self) -> bool {
+        false
+    }
+
+    /// Whether the connection still responds. Backends that cannot tell say it does.
+    fn is_alive(&mut self) -> bool {
+        true
+    }
+
+    /// Connects again after `is_alive` found the connection dropped. The REPL restores
+    /// the working directory afterwards.
+    fn reconnect(&mut self) -> BackendResult<()> {
+        Err("Reconnecting is not supported".into())
+    }
+
+    /// Resolves `path` to an absolute path without `.` or `..` components
+    fn realpath(&mut self, path: &Path) -> BackendResult<PathBuf> {
+        Ok(normalize_path(path))
+    }
+
+    /// Runs `cmd` remotely, streaming `stdin` into it and its stdout into `stdout`.
+    /// Returns the captured stderr and exit status.
+    fn exec_stream(
+        &mut self,
+        cmd: &str,
+        stdin: Option<&mut dyn Read>,
+        stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)>;
+
+    /// Runs `cmd` remotely and captures all of its output
+    fn exec(&mut self, cmd: &str) -> BackendResult<ExecOutput> {
+        let mut stdout = Vec::new();
+        let (stderr, exit_status) = self.exec_stream(cmd, None, &mut stdout)?;
+        Ok(ExecOutput {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr,
+            exit_status,
+        })
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat>;
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>>;
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>>;
+
+    /// Opens `path` for writing, truncating it. `size` is the exact number of bytes
+    /// that will be written, as some protocols (scp) need it up front.
+    fn open_write(&mut self, path: &Path, size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>>;
+
+    // No REPL command is bound to these yet, every backend still provides them
+    #[allow(dead_code)]
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()>;
+
+    /// Removes a file or an empty directory
+    #[allow(dead_code)]
+    fn remove(&mut self, path: &Path) -> BackendResult<()>;
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()>;
+}
+
+/// Lexically removes `.` and `..` components from `path`
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
@@ -1,6 +1,11 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use crate::config::StoreKind;
+
+/// Help of the `--insecure` flag of every TLS mode
+const INSECURE_HELP: &str = "Skip TLS certificate verification (self-signed appliances)";
 
 #[derive(Parser)]
 #[command(name = "trump")]
@@ -17,11 +22,230 @@ pub enum Commands {
     /// Connect to filesystem over ssh
     #[command(arg_required_else_help = true)]
     Ssh {
+        /// Target, a host profile, or a Host alias from ~/.ssh/config
+        #[arg(value_name = "[USER@]HOSTNAME[:PORT]")]
+        target: String,
+
+        #[command(flatten)]
+        ssh: SshArgs,
+    },
+
+    /// Connect to filesystem over sftp only (no remote shell)
+    #[command(arg_required_else_help = true)]
+    Sftp {
+        /// Target, or a Host alias from ~/.ssh/config
+        #[arg(value_name = "[USER@]HOSTNAME[:PORT]")]
+        target: String,
+
+        #[command(flatten)]
+        ssh: SshArgs,
+    },
+
+    /// Connect to filesystem over ftp (passive mode)
+    #[command(arg_required_else_help = true)]
+    Ftp {
         #[arg(value_name = "USER@HOSTNAME[:PORT]")]
         target: String,
 
-        /// Optional identity key file (private key)
-        #[arg(short, long)]
-        identity: Option<PathBuf>,
+        /// Upgrade the connection with AUTH TLS (explicit FTPS)
+        #[arg(long)]
+        tls: bool,
+
+        #[arg(long, help = INSECURE_HELP, requires = "tls")]
+        insecure: bool,
+    },
+
+    /// Connect to a WebDAV share (no remote shell)
+    #[command(arg_required_else_help = true)]
+    Webdav {
+        #[arg(value_name = "URL")]
+        url: String,
+
+        #[arg(long, help = INSECURE_HELP)]
+        insecure: bool,
+    },
+
+    /// Browse an S3-compatible bucket (no remote shell)
+    #[command(arg_required_else_help = true)]
+    S3 {
+        #[arg(value_name = "BUCKET[/PREFIX]")]
+        target: String,
+
+        /// Object store endpoint (MinIO, Ceph, ...)
+        #[arg(short, long, default_value = "https://s3.amazonaws.com")]
+        endpoint: String,
+
+        /// Region used for request signing
+        #[arg(short, long, default_value = "us-east-1")]
+        region: String,
+
+        #[arg(long, help = INSECURE_HELP)]
+        insecure: bool,
+    },
+
+    /// Connect to a shell or device CLI over telnet
+    #[command(arg_required_else_help = true)]
+    Telnet {
+        #[arg(value_name = "HOSTNAME[:PORT]")]
+        target: String,
+    },
+
+    /// Attach to a console on a serial line or pty
+    #[command(arg_required_else_help = true)]
+    Serial {
+        #[arg(value_name = "DEVICE")]
+        device: String,
+
+        /// Line speed
+        #[arg(short, long, default_value_t = 115200)]
+        baud: u32,
+    },
+
+    /// Connect to a running docker container
+    #[command(arg_required_else_help = true)]
+    Docker {
+        #[arg(value_name = "CONTAINER")]
+        container: String,
     },
+
+    /// Connect to a running podman container
+    #[command(arg_required_else_help = true)]
+    Podman {
+        #[arg(value_name = "CONTAINER")]
+        container: String,
+    },
+
+    /// Connect to a kubernetes pod through kubectl
+    #[command(arg_required_else_help = true)]
+    K8s {
+        #[arg(value_name = "NAMESPACE/POD[/CONTAINER]")]
+        target: String,
+    },
+
+    /// Use a local directory through the same REPL
+    Local {
+        /// Starting directory [default: current directory]
+        path: Option<PathBuf>,
+    },
+
+    /// Manage saved credentials
+    #[command(arg_required_else_help = true)]
+    Creds {
+        #[command(subcommand)]
+        action: CredsAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CredsAction {
+    /// List saved credentials, without their secrets
+    List,
+
+    /// Save a password (or a key or access key) for a target
+    #[command(arg_required_else_help = true)]
+    Add {
+        /// Key the connection modes look up, e.g. user@host:22 or bucket@host:443
+        #[arg(value_name = "USER@HOSTNAME:PORT")]
+        target: String,
+
+        /// Save this identity key and its passphrase instead of a password
+        #[arg(long, value_name = "KEY_FILE", conflicts_with = "access_key")]
+        key: Option<PathBuf>,
+
+        /// Save an object store access key pair instead of a password
+        #[arg(long)]
+        access_key: bool,
+    },
+
+    /// Delete the saved credentials of a target
+    #[command(arg_required_else_help = true)]
+    Remove {
+        #[arg(value_name = "USER@HOSTNAME:PORT")]
+        target: String,
+    },
+
+    /// Show or choose where newly saved secrets go
+    Store { kind: Option<StoreKind> },
+
+    /// Re-encrypt every saved secret under a new master password
+    RotateKey,
+
+    /// Write all saved credentials to a file protected by an export password
+    #[command(arg_required_else_help = true)]
+    Export { file: PathBuf },
+
+    /// Add the credentials from an exported file
+    #[command(arg_required_else_help = true)]
+    Import { file: PathBuf },
+}
+
+/// Options shared by the ssh based modes
+#[derive(Args, Clone)]
+pub struct SshArgs {
+    /// Optional identity key file (private key)
+    #[arg(short, long)]
+    pub identity: Option<PathBuf>,
+
+    /// OpenSSH certificate to present with the identity key, defaults to <IDENTITY>-cert.pub
+    #[arg(long, value_name = "CERT_FILE")]
+    pub certificate: Option<PathBuf>,
+
+    /// Jump host to connect through, repeat or separate with commas for several hops
+    #[arg(
+        short = 'J',
+        long,
+        value_name = "[USER@]HOSTNAME[:PORT]",
+        value_delimiter = ','
+    )]
+    pub jump: Vec<String>,
+
+    /// Command whose stdin/stdout carry the connection, %h, %p and %r are expanded
+    #[arg(long, value_name = "COMMAND", conflicts_with = "jump")]
+    pub proxy_command: Option<String>,
+
+    /// Authentication methods to try, in order, skipping those the server does not offer
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "agent,key,keyboard-interactive,password"
+    )]
+    pub auth_order: Vec<AuthMethod>,
+
+    /// Seconds to wait for the TCP connection and the ssh handshake, 0 waits forever
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    pub connect_timeout: u64,
+
+    /// Seconds between keepalive messages while connected, and of server silence before
+    /// the connection is checked, 0 disables both
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    pub keepalive: u32,
+
+    /// How to treat host keys missing from or not matching ~/.ssh/known_hosts
+    #[arg(long, value_enum, default_value_t = HostKeyChecking::Ask)]
+    pub strict_host_key_checking: HostKeyChecking,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Keys held by the ssh-agent at SSH_AUTH_SOCK
+    Agent,
+    /// The identity file, then a saved key
+    Key,
+    /// The saved password, then a password prompt
+    Password,
+    /// Server-driven prompts, e.g. one-time codes
+    KeyboardInteractive,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyChecking {
+    /// Show the fingerprint of unknown hosts and ask before trusting them
+    Ask,
+    /// Refuse unknown hosts
+    Yes,
+    /// Trust unknown hosts and only warn about changed keys
+    No,
+    /// Trust unknown hosts, refuse changed keys
+    AcceptNew,
 }
@@ -1,66 +1,380 @@
+use crate::credential_store;
 use aes_gcm::{
     Aes256Gcm, Key, Nonce,
     aead::{Aead, KeyInit},
 };
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{Engine as _, engine::general_purpose};
+use clap::ValueEnum;
+use colored::*;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-const KEY_FILE: &str = ".trump-key";
-const INFO_FILE: &str = ".trump-info";
+/// Key file of configs from before the vault, migrated and removed on first load
+const LEGACY_KEY_FILE: &str = ".trump-key";
+/// Config file in the home directory of older versions, moved on first load
+const LEGACY_INFO_FILE: &str = ".trump-info";
+/// Directory under `$XDG_CONFIG_HOME`
+const CONFIG_DIR: &str = "trump";
+const INFO_FILE: &str = "info.json";
+/// Hand written settings, next to the info file
+const PROFILES_FILE: &str = "config.toml";
+/// Supplies the master password non-interactively
+const MASTER_PASSWORD_ENV: &str = "TRUMP_MASTER_PASSWORD";
+/// Encrypted with the vault key to recognise a wrong master password
+const VAULT_CHECK: &str = "trump-vault";
+
+/// Key derived from the master password, unlocked at most once per run
+static VAULT_KEY: Mutex<Option<(VaultHeader, Key<Aes256Gcm>)>> = Mutex::new(None);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum AuthType {
     Password,
     KeyPath,
+    /// Encrypted `ACCESS_KEY_ID:SECRET_ACCESS_KEY` pair of an object store
+    AccessKey,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AuthData {
     pub auth_type: AuthType,
-    pub secret: String, // Encrypted password or path to key
+    pub secret: String, // Stored password (see `credential_store`) or path to key
+    /// Stored passphrase of a `KeyPath` key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase: Option<String>,
+}
+
+impl AuthType {
+    /// What the `secret` of this type is called, also its kind in the credential store
+    pub fn secret_kind(&self) -> &'static str {
+        match self {
+            AuthType::Password => "password",
+            AuthType::KeyPath => "key path",
+            AuthType::AccessKey => "access key",
+        }
+    }
+}
+
+impl AuthData {
+    /// References into the credential store, i.e. everything but a key path
+    pub fn secret_references(&self) -> impl Iterator<Item = &String> {
+        let secret = (!matches!(self.auth_type, AuthType::KeyPath)).then_some(&self.secret);
+        secret.into_iter().chain(self.passphrase.as_ref())
+    }
+
+    /// Copy with every secret reference replaced by `convert(kind, reference)`
+    fn convert_secrets(
+        &self,
+        mut convert: impl FnMut(&str, &str) -> Result<String, Box<dyn std::error::Error>>,
+    ) -> Result<AuthData, Box<dyn std::error::Error>> {
+        let mut converted = self.clone();
+        if !matches!(self.auth_type, AuthType::KeyPath) {
+            converted.secret = convert(self.auth_type.secret_kind(), &self.secret)?;
+        }
+        if let Some(passphrase) = &self.passphrase {
+            converted.passphrase = Some(convert("passphrase", passphrase)?);
+        }
+        Ok(converted)
+    }
+}
+
+/// How the vault key is derived from the master password
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VaultHeader {
+    pub kdf: String,
+    /// Base64 salt
+    pub salt: String,
+    /// Argon2 memory cost in KiB
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    /// `VAULT_CHECK` encrypted with the derived key
+    pub check: String,
+}
+
+/// Where newly saved secrets go
+#[derive(Serialize, Deserialize, ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// Encrypted with the vault key, inside the config file
+    #[default]
+    File,
+    /// The desktop keyring, through the Secret Service D-Bus API
+    Keyring,
+}
+
+/// Named connection settings, a `[hosts.NAME]` table of `config.toml`
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HostProfile {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub identity: Option<PathBuf>,
+    /// Jump hosts like `-J`, comma separated
+    pub jump: Option<String>,
+    /// Remote directory the session starts in
+    pub dir: Option<String>,
+    /// Exported to every command run on the host
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Commands run once after connecting
+    #[serde(default)]
+    pub startup: Vec<String>,
+}
+
+impl HostProfile {
+    /// The `[USER@]HOSTNAME[:PORT]` target of this profile
+    pub fn target(&self) -> String {
+        let mut target = self.host.clone();
+        if let Some(user) = &self.user {
+            target = format!("{}@{}", user, target);
+        }
+        if let Some(port) = self.port {
+            target = format!("{}:{}", target, port);
+        }
+        target
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Config {
+    #[serde(default)]
+    pub store: StoreKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault: Option<VaultHeader>,
     pub targets: HashMap<String, AuthData>,
 }
 
+/// Whether `config_key` has the `USER@HOSTNAME:PORT` form entries are looked up by
+pub fn is_config_key(config_key: &str) -> bool {
+    config_key
+        .split_once('@')
+        .and_then(|(user, host_port)| Some((user, host_port.rsplit_once(':')?)))
+        .is_some_and(|(user, (host, port))| {
+            !user.is_empty() && !host.is_empty() && port.parse::<u16>().is_ok()
+        })
+}
+
 /// Helper to get the path to a file in the user's home directory
 fn get_home_path(filename: &str) -> Option<PathBuf> {
     dirs::home_dir().map(|p| p.join(filename))
 }
 
-/// Retrieves or creates the encryption key (32 bytes)
-fn get_or_create_key() -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
-    let key_path = get_home_path(KEY_FILE).ok_or("Could not determine home directory")?;
+/// `$XDG_CONFIG_HOME/trump`, falling back to `~/.config/trump` like the XDG spec says
+fn get_config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| get_home_path(".config"))?;
+    Some(base.join(CONFIG_DIR))
+}
+
+fn get_info_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(get_config_dir()
+        .ok_or("Could not determine config directory")?
+        .join(INFO_FILE))
+}
 
-    if key_path.exists() {
-        let key_str = fs::read_to_string(&key_path)?;
-        let key_bytes = general_purpose::STANDARD.decode(key_str.trim())?;
-        if key_bytes.len() != 32 {
-            return Err(format!("Invalid key length in {:?}!", key_path).into());
+/// Writes `content` to `path` readable by the owner only. The content goes to a temporary
+/// file first that then replaces `path`, so a crash never leaves a half written file.
+fn write_private(path: &Path, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = path.parent().ok_or("Invalid config path")?;
+    let mut dir_builder = fs::DirBuilder::new();
+    dir_builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        dir_builder.mode(0o700);
+    }
+    dir_builder.create(dir)?;
+
+    let file_name = path.file_name().ok_or("Invalid config path")?;
+    let temp_path = dir.join(format!(".{}.tmp", file_name.to_string_lossy()));
+    // A leftover from a crash could have other permissions, start over
+    match fs::remove_file(&temp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&temp_path)
+        .map_err(|e| format!("Could not create {}: {}", temp_path.display(), e))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+        .map_err(|e| format!("Could not replace {}: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Refuses files holding secrets that other users can access
+fn check_private(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            eprintln!(
+                "{}",
+                format!("{} is accessible by other users!", path.display())
+                    .red()
+                    .bold()
+            );
+            return Err(format!(
+                "Refusing to load secrets from {} (mode {:o}), run `chmod 600 {}`!",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            )
+            .into());
         }
-        Ok(*Key::<Aes256Gcm>::from_slice(&key_bytes))
-    } else {
-        // Generate new key
-        let mut key_bytes = [0u8; 32];
-        rand::rng().fill_bytes(&mut key_bytes);
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Derives the 32 byte vault key from `password` with the parameters in `header`
+fn derive_key(
+    header: &VaultHeader,
+    password: &str,
+) -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
+    if header.kdf != "argon2id" {
+        return Err(format!("Unsupported key derivation {}!", header.kdf).into());
+    }
+    let salt = general_purpose::STANDARD.decode(&header.salt)?;
+    let params = Params::new(header.m_cost, header.t_cost, header.p_cost, Some(32))
+        .map_err(|e| format!("Invalid vault parameters: {}!", e))?;
+    let mut key_bytes = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), &salt, &mut key_bytes)
+        .map_err(|e| format!("Key derivation failure: {}!", e))?;
+    Ok(*Key::<Aes256Gcm>::from_slice(&key_bytes))
+}
+
+/// Reads a password from the `env` variable if that is set, otherwise asks for it
+fn read_password(prompt: &str, env: Option<&str>) -> std::io::Result<String> {
+    match env.and_then(|env| std::env::var(env).ok()) {
+        Some(password) if !password.is_empty() => Ok(password),
+        _ => rpassword::prompt_password(prompt),
+    }
+}
 
-        // Save base64 representation
-        let key_str = general_purpose::STANDARD.encode(key_bytes);
-        fs::write(key_path, key_str)?;
+/// Creates a vault under a newly chosen password, described as `what`
+fn create_vault(
+    what: &str,
+    env: Option<&str>,
+) -> Result<(VaultHeader, Key<Aes256Gcm>), Box<dyn std::error::Error>> {
+    let from_env = env.is_some_and(|env| std::env::var_os(env).is_some());
+    let password = loop {
+        let password = read_password(&format!("New {}: ", what), env)?;
+        if password.is_empty() {
+            return Err(format!("No {} given!", what).into());
+        }
+        if from_env || rpassword::prompt_password(format!("Repeat {}: ", what))? == password {
+            break password;
+        }
+        println!("{}", "✖ Passwords do not match!".red());
+    };
+
+    let mut salt = [0u8; 16];
+    rand::rng().fill_bytes(&mut salt);
+    let defaults = Params::default();
+    let mut header = VaultHeader {
+        kdf: "argon2id".to_string(),
+        salt: general_purpose::STANDARD.encode(salt),
+        m_cost: defaults.m_cost(),
+        t_cost: defaults.t_cost(),
+        p_cost: defaults.p_cost(),
+        check: String::new(),
+    };
+    let key = derive_key(&header, &password)?;
+    header.check = encrypt_with(&key, VAULT_CHECK)?;
+    Ok((header, key))
+}
+
+/// Asks for the password (described as `what`) of an existing vault, three tries like ssh
+fn unlock_vault(
+    header: &VaultHeader,
+    what: &str,
+    env: Option<&str>,
+) -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
+    for _ in 0..3 {
+        let password = read_password(&format!("Enter {}: ", what), env)?;
+        let key = derive_key(header, &password)?;
+        if decrypt_with(&key, &header.check).is_ok_and(|check| check == VAULT_CHECK) {
+            return Ok(key);
+        }
+        println!("{}", format!("✖ Wrong {}!", what).red());
+        if env.is_some_and(|env| std::env::var_os(env).is_some()) {
+            break;
+        }
+    }
+    Err("Could not unlock the credential vault!".into())
+}
 
-        Ok(*Key::<Aes256Gcm>::from_slice(&key_bytes))
+/// The vault key, unlocking the vault (or creating it) on first use
+fn vault_key() -> Result<Key<Aes256Gcm>, Box<dyn std::error::Error>> {
+    let mut cached = VAULT_KEY.lock().map_err(|_| "Vault lock poisoned!")?;
+    if let Some((_, key)) = cached.as_ref() {
+        return Ok(*key);
     }
+
+    let unlocked = match read_config(&get_info_path()?)?.vault {
+        Some(header) => {
+            let key = unlock_vault(&header, "master password", Some(MASTER_PASSWORD_ENV))?;
+            (header, key)
+        }
+        None => {
+            println!(
+                "{}",
+                "Saved credentials are protected by a master password.".cyan()
+            );
+            create_vault("master password", Some(MASTER_PASSWORD_ENV))?
+        }
+    };
+    let key = unlocked.1;
+    *cached = Some(unlocked);
+    Ok(key)
+}
+
+/// Unlocks the vault under `password` for this run without reading or writing any file
+#[cfg(test)]
+pub fn unlock_test_vault(password: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let header = VaultHeader {
+        kdf: "argon2id".to_string(),
+        salt: general_purpose::STANDARD.encode(b"trump-test-salt!"),
+        m_cost: 1024,
+        t_cost: 1,
+        p_cost: 1,
+        check: String::new(),
+    };
+    let key = derive_key(&header, password)?;
+    *VAULT_KEY.lock().map_err(|_| "Vault lock poisoned!")? = Some((header, key));
+    Ok(())
 }
 
 pub fn encrypt(data: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let key = get_or_create_key()?;
-    let cipher = Aes256Gcm::new(&key);
+    encrypt_with(&vault_key()?, data)
+}
+
+pub fn decrypt(encrypted_data: &str) -> Result<String, Box<dyn std::error::Error>> {
+    decrypt_with(&vault_key()?, encrypted_data)
+}
+
+fn encrypt_with(key: &Key<Aes256Gcm>, data: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let cipher = Aes256Gcm::new(key);
 
     // Generate unique nonce (96-bits)
     let mut nonce_bytes = [0u8; 12];
@@ -78,9 +392,11 @@ pub fn encrypt(data: &str) -> Result<String, Box<dyn std::error::Error>> {
     Ok(general_purpose::STANDARD.encode(combined))
 }
 
-pub fn decrypt(encrypted_data: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let key = get_or_create_key()?;
-    let cipher = Aes256Gcm::new(&key);
+fn decrypt_with(
+    key: &Key<Aes256Gcm>,
+    encrypted_data: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let cipher = Aes256Gcm::new(key);
 
     let combined = general_purpose::STANDARD.decode(encrypted_data)?;
     if combined.len() < 12 {
@@ -97,10 +413,25 @@ pub fn decrypt(encrypted_data: &str) -> Result<String, Box<dyn std::error::Error
     Ok(String::from_utf8(plaintext)?)
 }
 
+/// Loads the config, moving secrets encrypted with a legacy key file into the vault
 pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
-    let config_path = get_home_path(INFO_FILE).ok_or("Could not determine home directory")?;
+    let config_path = get_info_path()?;
+    let legacy_info =
+        get_home_path(LEGACY_INFO_FILE).ok_or("Could not determine home directory")?;
+    if !config_path.exists() && legacy_info.exists() {
+        migrate_legacy_info(&legacy_info, &config_path)?;
+    }
+    let mut config = read_config(&config_path)?;
+    let legacy_path = get_home_path(LEGACY_KEY_FILE).ok_or("Could not determine home directory")?;
+    if config.vault.is_none() && legacy_path.exists() {
+        migrate_legacy_key(&mut config, &legacy_path)?;
+    }
+    Ok(config)
+}
 
+fn read_config(config_path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
     if config_path.exists() {
+        check_private(config_path)?;
         let content = fs::read_to_string(config_path)?;
         let config: Config = serde_json::from_str(&content)?;
         Ok(config)
@@ -109,9 +440,598 @@ pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     }
 }
 
+/// Looks up the host profile `name` in `config.toml`. Mistakes in the file only fail the
+/// lookup when they concern this profile, otherwise they are reported and skipped.
+pub fn load_profile(name: &str) -> Result<Option<HostProfile>, Box<dyn std::error::Error>> {
+    let path = get_info_path()?.with_file_name(PROFILES_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    parse_profile(&content, name).map_err(|e| format!("Invalid {}: {}", path.display(), e).into())
+}
+
+fn parse_profile(content: &str, name: &str) -> Result<Option<HostProfile>, String> {
+    let table: toml::Table = match toml::from_str(content) {
+        Ok(table) => table,
+        // Without a table to look at, only the text can tell if the profile is in there
+        Err(e) if content.contains(&format!("hosts.{}", name)) => return Err(e.to_string()),
+        Err(e) => {
+            eprintln!(
+                "{} {}",
+                "Warning: Ignoring host profiles:".yellow(),
+                e.to_string().trim()
+            );
+            return Ok(None);
+        }
+    };
+    let Some(hosts) = table.get("hosts") else {
+        return Ok(None);
+    };
+    let hosts = hosts
+        .as_table()
+        .ok_or("`hosts` has to be a table of profiles")?;
+
+    let mut found = None;
+    for (host_name, value) in hosts {
+        match (value.clone().try_into::<HostProfile>(), host_name == name) {
+            (Ok(profile), true) => found = Some(profile),
+            (Err(e), true) => return Err(format!("[hosts.{}]: {}", host_name, e)),
+            (Err(e), false) => eprintln!(
+                "{} [hosts.{}]: {}",
+                "Warning: Ignoring host profile".yellow(),
+                host_name,
+                e.to_string().trim()
+            ),
+            (Ok(_), false) => {}
+        }
+    }
+    Ok(found)
+}
+
+/// Moves the config file of older versions out of the home directory, tightening its
+/// permissions on the way
+fn migrate_legacy_info(
+    legacy_path: &Path,
+    config_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    write_private(config_path, &fs::read_to_string(legacy_path)?)?;
+    fs::remove_file(legacy_path)?;
+    println!(
+        "{} {} -> {}",
+        "Moved config:".dimmed(),
+        legacy_path.display(),
+        config_path.display()
+    );
+    Ok(())
+}
+
+/// Re-encrypts every secret of `config` from the key in `legacy_path` under a new vault
+/// and deletes the key file
+fn migrate_legacy_key(
+    config: &mut Config,
+    legacy_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !config.targets.is_empty() {
+        let key_bytes =
+            general_purpose::STANDARD.decode(fs::read_to_string(legacy_path)?.trim())?;
+        if key_bytes.len() != 32 {
+            return Err(format!("Invalid key length in {:?}!", legacy_path).into());
+        }
+        let legacy = *Key::<Aes256Gcm>::from_slice(&key_bytes);
+
+        println!(
+            "{} {}",
+            "Saved credentials are still encrypted with".yellow(),
+            legacy_path.display()
+        );
+        let (header, key) = create_vault("master password", Some(MASTER_PASSWORD_ENV))?;
+        for auth in config.targets.values_mut() {
+            *auth = auth
+                .convert_secrets(|_, secret| encrypt_with(&key, &decrypt_with(&legacy, secret)?))?;
+        }
+        config.vault = Some(header.clone());
+        save_config(config)?;
+        *VAULT_KEY.lock().map_err(|_| "Vault lock poisoned!")? = Some((header, key));
+        println!(
+            "{}",
+            format!(
+                "✔ Moved {} saved credential(s) into the vault.",
+                config.targets.len()
+            )
+            .green()
+            .bold()
+        );
+    }
+    fs::remove_file(legacy_path)?;
+    Ok(())
+}
+
+/// Re-encrypts every secret kept in the file store under a new master password and salt,
+/// returning how many were rotated
+pub fn rotate_key(config: &mut Config) -> Result<usize, Box<dyn std::error::Error>> {
+    if config.vault.is_none() {
+        return Err("There is no vault yet, nothing to rotate!".into());
+    }
+    let old_key = vault_key()?;
+    println!("{}", "Choose the new master password.".cyan());
+    let (header, key) = create_vault("master password", None)?;
+
+    let mut rotated = 0;
+    for auth in config.targets.values_mut() {
+        *auth = auth.convert_secrets(|_, reference| {
+            if credential_store::holding(reference).name() != "file" {
+                return Ok(reference.to_string());
+            }
+            rotated += 1;
+            encrypt_with(&key, &decrypt_with(&old_key, reference)?)
+        })?;
+    }
+    config.vault = Some(header.clone());
+    save_config(config)?;
+    *VAULT_KEY.lock().map_err(|_| "Vault lock poisoned!")? = Some((header, key));
+    Ok(rotated)
+}
+
+/// Writes every saved credential to `path` as a standalone vault, re-encrypted under an
+/// export password. Returns how many entries were written.
+pub fn export_credentials(
+    config: &Config,
+    path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // Unlock the secrets before asking for the export password
+    let mut revealed = HashMap::new();
+    for (config_key, auth) in &config.targets {
+        let auth = auth.convert_secrets(|_, reference| credential_store::load_secret(reference))?;
+        revealed.insert(config_key.clone(), auth);
+    }
+
+    let (header, key) = create_vault("export password", None)?;
+    write_export(revealed, header, &key, path)
+}
+
+/// Writes the `revealed` entries to `path`, encrypted with the `key` of `header`
+fn write_export(
+    revealed: HashMap<String, AuthData>,
+    header: VaultHeader,
+    key: &Key<Aes256Gcm>,
+    path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut export = Config {
+        store: StoreKind::File,
+        vault: Some(header),
+        ..Default::default()
+    };
+    for (config_key, auth) in revealed {
+        let auth = auth.convert_secrets(|_, secret| encrypt_with(key, secret))?;
+        export.targets.insert(config_key, auth);
+    }
+    write_private(path, &serde_json::to_string_pretty(&export)?)?;
+    Ok(export.targets.len())
+}
+
+/// Adds the credentials exported to `path` to `config`, storing the secrets in the
+/// configured credential store. Returns how many entries were imported.
+pub fn import_credentials(
+    config: Config,
+    path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let import: Config = serde_json::from_str(&content)?;
+    let header = import
+        .vault
+        .as_ref()
+        .ok_or("Not an export, the vault header is missing!")?;
+    let key = unlock_vault(header, "export password", None)?;
+    import_entries(config, &import, &key, &get_info_path()?)
+}
+
+/// Adds the entries of `import`, encrypted with `key`, to `config` and saves it to
+/// `config_path`
+fn import_entries(
+    mut config: Config,
+    import: &Config,
+    key: &Key<Aes256Gcm>,
+    config_path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let store = credential_store::for_config(&config);
+    let mut imported: Vec<(AuthData, Option<AuthData>)> = Vec::new();
+    let mut result = Ok(());
+    for (config_key, auth) in &import.targets {
+        let stored = auth.convert_secrets(|kind, secret| {
+            store.store(config_key, kind, &decrypt_with(key, secret)?)
+        });
+        match stored {
+            Ok(auth) => {
+                let old = config.targets.insert(config_key.clone(), auth.clone());
+                imported.push((auth, old));
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    // Like `save_entry`, clean up the old secrets after saving, or the new ones on failure
+    if let Err(e) = result.and_then(|()| save_config_at(config_path, &config)) {
+        for (auth, old) in &imported {
+            forget_unreferenced(auth, old.as_ref());
+        }
+        return Err(e);
+    }
+    for (auth, old) in &imported {
+        if let Some(old) = old {
+            forget_unreferenced(old, Some(auth));
+        }
+    }
+    Ok(imported.len())
+}
+
+/// Asks whether to store `password` for `config_key` and saves it encrypted on confirmation
+pub fn offer_to_save_password(
+    config: Config,
+    config_key: String,
+    password: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    offer_to_save(config, config_key, AuthType::Password, password, "password")
+}
+
+/// Asks whether to store `secret` (described as `what`) for `config_key` and saves it
+/// in the configured credential store on confirmation
+pub fn offer_to_save(
+    config: Config,
+    config_key: String,
+    auth_type: AuthType,
+    secret: &str,
+    what: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !confirm_save(what)? {
+        return Ok(());
+    }
+    let stored = credential_store::for_config(&config).store(&config_key, what, secret);
+    match stored {
+        Ok(reference) => {
+            let auth = AuthData {
+                auth_type,
+                secret: reference,
+                passphrase: None,
+            };
+            save_entry(config, config_key, auth)?;
+        }
+        Err(e) => eprintln!("{} {}.", "✖ Could not save credentials:".red(), e),
+    }
+    Ok(())
+}
+
+/// Asks whether to remember `key_path` with its `passphrase` for `config_key` and saves
+/// them on confirmation, the passphrase in the configured credential store
+pub fn offer_to_save_passphrase(
+    config: Config,
+    config_key: String,
+    key_path: &Path,
+    passphrase: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !confirm_save("passphrase")? {
+        return Ok(());
+    }
+    let stored = credential_store::for_config(&config).store(&config_key, "passphrase", passphrase);
+    match stored {
+        Ok(reference) => {
+            let auth = AuthData {
+                auth_type: AuthType::KeyPath,
+                secret: key_path.to_string_lossy().to_string(),
+                passphrase: Some(reference),
+            };
+            save_entry(config, config_key, auth)?;
+        }
+        Err(e) => eprintln!("{} {}.", "✖ Could not save credentials:".red(), e),
+    }
+    Ok(())
+}
+
+/// Saves `auth` as the entry for `config_key`, deleting secrets of the entry it replaces
+pub fn save_entry(
+    mut config: Config,
+    config_key: String,
+    auth: AuthData,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let old = config.targets.insert(config_key, auth.clone());
+    // Secrets only become unreachable once the config no longer points at them, so the
+    // store is cleaned up after saving: of the old secrets on success, else of the new ones
+    if let Err(e) = save_config(&config) {
+        forget_unreferenced(&auth, old.as_ref());
+        return Err(e);
+    }
+    if let Some(old) = old {
+        forget_unreferenced(&old, Some(&auth));
+    }
+    println!("{}", "✔ Credentials saved.".green().bold());
+    Ok(())
+}
+
+/// Deletes the stored secrets of `dropped` that `kept` does not refer to as well
+fn forget_unreferenced(dropped: &AuthData, kept: Option<&AuthData>) {
+    for reference in dropped.secret_references() {
+        if kept.is_none_or(|kept| kept.secret_references().all(|kept| kept != reference)) {
+            let _ = credential_store::holding(reference).delete(reference);
+        }
+    }
+}
+
+fn confirm_save(what: &str) -> std::io::Result<bool> {
+    print!(
+        "{} ",
+        format!("Do you want to save this {} for auto-login? [y/N]", what)
+            .yellow()
+            .bold()
+    );
+    std::io::stdout().flush()?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
 pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
-    let config_path = get_home_path(INFO_FILE).ok_or("Could not determine home directory")?;
-    let content = serde_json::to_string_pretty(config)?;
-    fs::write(config_path, content)?;
+    save_config_at(&get_info_path()?, config)
+}
+
+fn save_config_at(config_path: &Path, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    // A vault created while this config was loaded needs its header written out too
+    let mut config = config.clone();
+    if config.vault.is_none() {
+        let cached = VAULT_KEY.lock().map_err(|_| "Vault lock poisoned!")?;
+        config.vault = cached.as_ref().map(|(header, _)| header.clone());
+    }
+    let content = serde_json::to_string_pretty(&config)?;
+    write_private(config_path, &content)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Vault header with cheap Argon2 costs, the defaults are slow in debug builds
+    fn test_header(salt: &[u8]) -> VaultHeader {
+        VaultHeader {
+            kdf: "argon2id".to_string(),
+            salt: general_purpose::STANDARD.encode(salt),
+            m_cost: 1024,
+            t_cost: 1,
+            p_cost: 1,
+            check: String::new(),
+        }
+    }
+
+    #[test]
+    fn derive_key_depends_on_password_and_salt() {
+        let header = test_header(b"0123456789abcdef");
+        let key = derive_key(&header, "hunter2").unwrap();
+        assert_eq!(key, derive_key(&header, "hunter2").unwrap());
+        assert_ne!(key, derive_key(&header, "hunter3").unwrap());
+        assert_ne!(
+            key,
+            derive_key(&test_header(b"fedcba9876543210"), "hunter2").unwrap()
+        );
+    }
+
+    #[test]
+    fn derive_key_rejects_unknown_kdf() {
+        let header = VaultHeader {
+            kdf: "scrypt".to_string(),
+            ..test_header(b"0123456789abcdef")
+        };
+        assert!(derive_key(&header, "hunter2").is_err());
+    }
+
+    #[test]
+    fn encrypt_round_trips_and_rejects_wrong_password() {
+        let header = test_header(b"0123456789abcdef");
+        let key = derive_key(&header, "hunter2").unwrap();
+        let encrypted = encrypt_with(&key, "s3cret pässword").unwrap();
+        assert_ne!(encrypted, encrypt_with(&key, "s3cret pässword").unwrap());
+        assert_eq!(decrypt_with(&key, &encrypted).unwrap(), "s3cret pässword");
+
+        let wrong = derive_key(&header, "hunter3").unwrap();
+        assert!(decrypt_with(&wrong, &encrypted).is_err());
+        assert!(decrypt_with(&key, "c2hvcnQ=").is_err());
+        assert!(decrypt_with(&key, "not base64!").is_err());
+    }
+
+    #[test]
+    fn is_config_key_wants_user_host_and_port() {
+        assert!(is_config_key("alice@example.com:22"));
+        assert!(is_config_key("bucket@s3.example.com:443"));
+        assert!(is_config_key("alice@[::1]:2222"));
+        for target in [
+            "example.com:22",
+            "alice@example.com",
+            "@example.com:22",
+            "alice@:22",
+            "alice@example.com:",
+            "alice@example.com:ssh",
+            "alice@example.com:65536",
+        ] {
+            assert!(!is_config_key(target), "{:?} was accepted", target);
+        }
+    }
+
+    fn entry(auth_type: AuthType, secret: String, passphrase: Option<String>) -> AuthData {
+        AuthData {
+            auth_type,
+            secret,
+            passphrase,
+        }
+    }
+
+    #[test]
+    fn export_import_round_trips() {
+        unlock_test_vault("correct horse").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        let alice = "alice@example.com:22";
+        let bob = "bob@example.com:22";
+        let bucket = "bucket@s3.example.com:443";
+        config.targets.insert(
+            alice.to_string(),
+            entry(AuthType::Password, encrypt("s3cret").unwrap(), None),
+        );
+        config.targets.insert(
+            bob.to_string(),
+            entry(
+                AuthType::KeyPath,
+                "/keys/id_ed25519".to_string(),
+                Some(encrypt("opensesame").unwrap()),
+            ),
+        );
+        config.targets.insert(
+            bucket.to_string(),
+            entry(AuthType::AccessKey, encrypt("id:key").unwrap(), None),
+        );
+
+        let mut revealed = HashMap::new();
+        for (config_key, auth) in &config.targets {
+            revealed.insert(
+                config_key.clone(),
+                auth.convert_secrets(|_, r| decrypt(r)).unwrap(),
+            );
+        }
+        let mut header = test_header(b"export-salt-1234");
+        let key = derive_key(&header, "export").unwrap();
+        header.check = encrypt_with(&key, VAULT_CHECK).unwrap();
+        let export_path = dir.path().join("export.json");
+        assert_eq!(
+            write_export(revealed, header, &key, &export_path).unwrap(),
+            3
+        );
+
+        let content = fs::read_to_string(&export_path).unwrap();
+        assert!(!content.contains("s3cret") && !content.contains("opensesame"));
+        let import: Config = serde_json::from_str(&content).unwrap();
+        assert_eq!(import.store, StoreKind::File);
+
+        // A wrong export key imports nothing and leaves the config alone
+        let config_path = dir.path().join(INFO_FILE);
+        let wrong = derive_key(&test_header(b"export-salt-1234"), "wrong").unwrap();
+        assert!(import_entries(Config::default(), &import, &wrong, &config_path).is_err());
+        assert!(!config_path.exists());
+
+        // Imported entries replace those of the same target
+        let mut existing = Config::default();
+        existing.targets.insert(
+            alice.to_string(),
+            entry(AuthType::Password, encrypt("old").unwrap(), None),
+        );
+        assert_eq!(
+            import_entries(existing, &import, &key, &config_path).unwrap(),
+            3
+        );
+
+        let imported = read_config(&config_path).unwrap();
+        assert_eq!(imported.targets.len(), 3);
+        assert_eq!(decrypt(&imported.targets[alice].secret).unwrap(), "s3cret");
+        assert_eq!(imported.targets[bob].secret, "/keys/id_ed25519");
+        let passphrase = imported.targets[bob].passphrase.as_deref().unwrap();
+        assert_eq!(decrypt(passphrase).unwrap(), "opensesame");
+        assert_eq!(decrypt(&imported.targets[bucket].secret).unwrap(), "id:key");
+    }
+
+    /// Empty scratch directory unique to this test run
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("trump-{}-{}", std::process::id(), name));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_is_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("write-private");
+        let path = dir.join("nested").join(INFO_FILE);
+
+        write_private(&path, "first").unwrap();
+        // A stale temporary file from a crash is replaced rather than reused
+        fs::write(dir.join("nested").join(".info.json.tmp"), "stale").unwrap();
+        write_private(&path, "second").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(mode(&dir.join("nested")), 0o700);
+        assert!(!dir.join("nested").join(".info.json.tmp").exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_private_refuses_shared_files() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = scratch_dir("check-private");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(INFO_FILE);
+        fs::write(&path, "{}").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(check_private(&path).is_err());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o620)).unwrap();
+        assert!(check_private(&path).is_err());
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(check_private(&path).is_ok());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn host_profile_target_includes_set_parts() {
+        let mut profile = HostProfile {
+            host: "db.internal".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(profile.target(), "db.internal");
+        profile.user = Some("deploy".to_string());
+        assert_eq!(profile.target(), "deploy@db.internal");
+        profile.port = Some(2222);
+        assert_eq!(profile.target(), "deploy@db.internal:2222");
+    }
+
+    const PROFILES: &str = r#"
+[hosts.web]
+user = "deploy"
+host = "web.example.com"
+port = 2200
+dir = "~/app"
+env = { RUST_LOG = "debug" }
+startup = ["uptime"]
+
+[hosts.broken]
+host = "x"
+colour = "blue"
+"#;
+
+    #[test]
+    fn parse_profile_finds_named_host() {
+        let profile = parse_profile(PROFILES, "web").unwrap().unwrap();
+        assert_eq!(profile.target(), "deploy@web.example.com:2200");
+        assert_eq!(profile.dir.as_deref(), Some("~/app"));
+        assert_eq!(profile.env["RUST_LOG"], "debug");
+        assert_eq!(profile.startup, ["uptime"]);
+
+        assert!(parse_profile(PROFILES, "missing").unwrap().is_none());
+        assert!(parse_profile("", "web").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_profile_only_fails_for_the_named_host() {
+        // Unknown keys are an error for that profile only
+        assert!(parse_profile(PROFILES, "broken").is_err());
+
+        // A syntax error fails the lookup only when it mentions the profile
+        let invalid = "[hosts.web]\nhost = \"unterminated\n";
+        assert!(parse_profile(invalid, "web").is_err());
+        assert!(parse_profile(invalid, "other").unwrap().is_none());
+
+        assert!(parse_profile("hosts = 1", "web").is_err());
+    }
+}
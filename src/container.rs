@@ -0,0 +1,319 @@
+use crate::backend::{BackendResult, DirEntry, ExecOutput, FileStat, RemoteBackend, RemoteWrite};
+use crate::local::stream_command;
+use crate::shell;
+use colored::*;
+use std::{
+    error::Error,
+    ffi::OsStr,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+/// Shell reached through a local CLI that forwards stdio (`docker exec -i <container>`,
+/// `kubectl exec -i <pod> --`).
+/// Every operation runs `sh -c` inside the target, with paths passed as positional
+/// arguments so they never need quoting.
+pub struct ContainerClient {
+    program: String,
+    exec_args: Vec<String>,
+    name: String,
+    pub current_directory: PathBuf,
+}
+
+/// Connects to a running container through the `docker` or `podman` CLI
+pub fn container_connect(
+    runtime: &str,
+    container: &str,
+) -> Result<ContainerClient, Box<dyn Error>> {
+    exec_connect(
+        runtime,
+        vec!["exec".to_string(), "-i".to_string(), container.to_string()],
+        format!("{}:{}", runtime, container),
+    )
+}
+
+/// Connects to a pod through `kubectl exec`, `target` being `NAMESPACE/POD[/CONTAINER]`
+pub fn pod_connect(target: &str) -> Result<ContainerClient, Box<dyn Error>> {
+    let exec_args = pod_exec_args(target).inspect_err(|_| {
+        eprintln!("{}", "Misformatted NAMESPACE/POD[/CONTAINER]!".red().bold());
+    })?;
+    exec_connect("kubectl", exec_args, format!("k8s:{}", target))
+}
+
+/// `kubectl` arguments that exec into a `NAMESPACE/POD[/CONTAINER]` target
+fn pod_exec_args(target: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let parts: Vec<&str> = target.split('/').collect();
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|part| part.is_empty()) {
+        return Err("Incorrect pod address formatting!".into());
+    }
+
+    let mut exec_args = vec![
+        "exec".to_string(),
+        "-i".to_string(),
+        "-n".to_string(),
+        parts[0].to_string(),
+        parts[1].to_string(),
+    ];
+    if let Some(container) = parts.get(2) {
+        exec_args.push("-c".to_string());
+        exec_args.push(container.to_string());
+    }
+    exec_args.push("--".to_string());
+    Ok(exec_args)
+}
+
+fn exec_connect(
+    program: &str,
+    exec_args: Vec<String>,
+    name: String,
+) -> Result<ContainerClient, Box<dyn Error>> {
+    let mut client = ContainerClient {
+        program: program.to_string(),
+        exec_args,
+        name,
+        current_directory: PathBuf::new(),
+    };
+
+    println!("{} {}.", "Connecting to".cyan(), client.name);
+    let output = client
+        .run("pwd", &[])
+        .map_err(|e| format!("Could not exec into {}: {}", client.name, e))?;
+    client.current_directory = PathBuf::from(output.stdout.trim());
+    println!("{}", "✔ Connected.".green().bold());
+
+    Ok(client)
+}
+
+impl ContainerClient {
+    /// Builds `<program> <exec_args> sh -c <script> sh <args>`
+    fn command(&self, script: &str, args: &[&OsStr]) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.exec_args)
+            .arg("sh")
+            .arg("-c")
+            .arg(script)
+            .arg("sh")
+            .args(args);
+        command
+    }
+
+    /// Runs `script` to completion, failing on a non-zero exit status
+    fn run(&self, script: &str, args: &[&OsStr]) -> BackendResult<ExecOutput> {
+        let mut stdout = Vec::new();
+        let (stderr, exit_status) =
+            stream_command(&mut self.command(script, args), None, &mut stdout)
+                .map_err(|e| format!("Failed to run {}: {}", self.program, e))?;
+        if exit_status != 0 {
+            return Err(stderr.trim().to_string().into());
+        }
+        Ok(ExecOutput {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr,
+            exit_status,
+        })
+    }
+}
+
+/// Stdout of a streaming `cat`, surfacing the exit status once drained
+struct ChildReader {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for ChildReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = self.child.stderr.take() {
+                pipe.read_to_string(&mut stderr)?;
+            }
+            if !self.child.wait()?.success() {
+                return Err(io::Error::other(stderr.trim().to_string()));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for ChildReader {
+    fn drop(&mut self) {
+        // Reap the child even when the caller stopped reading early
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Stdin of a streaming `cat > file`, the write landed once the child exited
+struct ChildWriter {
+    child: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Write for ChildWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.stdin {
+            Some(stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl RemoteWrite for ChildWriter {
+    fn finish(mut self: Box<Self>) -> BackendResult<()> {
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(format!("Remote write failed: {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ChildWriter {
+    fn drop(&mut self) {
+        // Reap the child of an abandoned write too
+        drop(self.stdin.take());
+        self.child.wait().ok();
+    }
+}
+
+impl RemoteBackend for ContainerClient {
+    fn label(&self) -> String {
+        self.name.clone()
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn exec_stream(
+        &mut self,
+        cmd: &str,
+        stdin: Option<&mut dyn Read>,
+        stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        stream_command(&mut self.command(cmd, &[]), stdin, stdout)
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        let output = self.run(shell::STAT_SCRIPT, &[path.as_os_str()])?;
+        shell::parse_stat(&output.stdout)
+            .ok_or_else(|| format!("Unexpected stat output: {}", output.stdout).into())
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        let output = self.run(shell::READ_DIR_SCRIPT, &[path.as_os_str()])?;
+        Ok(shell::parse_read_dir(&output.stdout))
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        let mut child = self
+            .command("cat -- \"$1\"", &[path.as_os_str()])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("Failed to take stdout");
+        Ok(Box::new(ChildReader { child, stdout }))
+    }
+
+    fn open_write(&mut self, path: &Path, _size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        let mut child = self
+            .command("cat > \"$1\"", &[path.as_os_str()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        Ok(Box::new(ChildWriter { child, stdin }))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        self.run(shell::RENAME_SCRIPT, &[from.as_os_str(), to.as_os_str()])?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        self.run(shell::REMOVE_SCRIPT, &[path.as_os_str()])?;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        self.run(shell::MKDIR_SCRIPT, &[path.as_os_str()])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn pod_exec_args_splits_targets() {
+        assert_eq!(
+            pod_exec_args("prod/web").unwrap(),
+            ["exec", "-i", "-n", "prod", "web", "--"]
+        );
+        assert_eq!(
+            pod_exec_args("prod/web/app").unwrap(),
+            ["exec", "-i", "-n", "prod", "web", "-c", "app", "--"]
+        );
+    }
+
+    #[test]
+    fn pod_exec_args_rejects_misformatted_targets() {
+        for target in [
+            "",
+            "web",
+            "/web",
+            "prod/",
+            "prod//app",
+            "prod/web/",
+            "a/b/c/d",
+        ] {
+            assert!(pod_exec_args(target).is_err(), "{:?} was accepted", target);
+        }
+    }
+
+    #[test]
+    fn pod_connect_runs_shell_after_kubectl_args() {
+        // Stands in for kubectl: records its arguments and runs what follows `--`
+        let dir = tempfile::tempdir().unwrap();
+        let shim = dir.path().join("kubectl");
+        fs::write(
+            &shim,
+            "printf '%s\\n' \"$@\" >> \"$0.log\"\n\
+             while [ \"$1\" != -- ]; do shift; done\n\
+             shift\n\
+             exec \"$@\"\n",
+        )
+        .unwrap();
+
+        let mut exec_args = vec![shim.to_string_lossy().to_string()];
+        exec_args.extend(pod_exec_args("prod/web/app").unwrap());
+        let client = exec_connect("sh", exec_args, "k8s:prod/web/app".to_string()).unwrap();
+        assert_eq!(client.current_directory, std::env::current_dir().unwrap());
+
+        let argv = fs::read_to_string(dir.path().join("kubectl.log")).unwrap();
+        assert_eq!(
+            argv.lines().collect::<Vec<_>>(),
+            [
+                "exec", "-i", "-n", "prod", "web", "-c", "app", "--", "sh", "-c", "pwd", "sh"
+            ]
+        );
+    }
+}
@@ -0,0 +1,367 @@
+//! Where saved secrets live. `AuthData` only keeps a reference that the store resolves:
+//! for the AES file store that is the ciphertext itself, for the keyring it names an item
+//! of the desktop's Secret Service.
+
+use crate::config::{self, Config, StoreKind};
+use secret_service::{EncryptionType, blocking::SecretService};
+use std::{collections::HashMap, error::Error};
+use zbus::blocking::{Connection, connection::Builder};
+
+/// Prefix of references to keyring items
+const KEYRING_PREFIX: &str = "keyring:";
+
+pub trait CredentialStore {
+    /// Name as used for `store` in the config
+    fn name(&self) -> &'static str;
+
+    /// Stores `secret`, a `kind` of secret (e.g. "password") for `target`, and returns
+    /// the reference to keep in the config
+    fn store(&self, target: &str, kind: &str, secret: &str) -> Result<String, Box<dyn Error>>;
+
+    fn load(&self, reference: &str) -> Result<String, Box<dyn Error>>;
+
+    fn delete(&self, reference: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// Secrets encrypted with the vault key, inline in the config file
+pub struct FileStore;
+
+impl CredentialStore for FileStore {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn store(&self, _target: &str, _kind: &str, secret: &str) -> Result<String, Box<dyn Error>> {
+        config::encrypt(secret)
+    }
+
+    fn load(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+        config::decrypt(reference)
+    }
+
+    /// The secret goes away with the config entry holding it
+    fn delete(&self, _reference: &str) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Secrets kept in the default collection of the Secret Service (GNOME Keyring, KWallet)
+/// on D-Bus
+pub struct KeyringStore {
+    /// Bus the Secret Service is on, the session bus if `None`
+    bus_address: Option<String>,
+}
+
+impl KeyringStore {
+    pub fn new(bus_address: Option<String>) -> Self {
+        KeyringStore { bus_address }
+    }
+
+    fn attributes<'a>(target: &'a str, kind: &'a str) -> HashMap<&'a str, &'a str> {
+        HashMap::from([("application", "trump"), ("target", target), ("kind", kind)])
+    }
+
+    /// Splits a `keyring:KIND:TARGET` reference
+    fn parse(reference: &str) -> Result<(&str, &str), Box<dyn Error>> {
+        reference
+            .strip_prefix(KEYRING_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or_else(|| format!("Invalid keyring reference {}!", reference).into())
+    }
+
+    /// Fails unless the Secret Service can be reached
+    pub fn check(&self) -> Result<(), Box<dyn Error>> {
+        self.connect().map(|_| ())
+    }
+
+    fn connect(&self) -> Result<SecretService<'static>, Box<dyn Error>> {
+        let connection = match &self.bus_address {
+            Some(address) => Builder::address(address.as_str())?.build(),
+            None => Connection::session(),
+        }
+        .map_err(|e| format!("Could not reach the Secret Service: {}", e))?;
+        // Not every service implements encrypted transfers
+        SecretService::connect_with_existing(EncryptionType::Dh, connection.clone())
+            .or_else(|_| SecretService::connect_with_existing(EncryptionType::Plain, connection))
+            .map_err(|e| format!("Could not reach the Secret Service: {}", e).into())
+    }
+}
+
+impl CredentialStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn store(&self, target: &str, kind: &str, secret: &str) -> Result<String, Box<dyn Error>> {
+        let service = self.connect()?;
+        let collection = service.get_default_collection()?;
+        collection.ensure_unlocked()?;
+        collection.create_item(
+            &format!("trump {} for {}", kind, target),
+            Self::attributes(target, kind),
+            secret.as_bytes(),
+            true,
+            "text/plain",
+        )?;
+        Ok(format!("{}{}:{}", KEYRING_PREFIX, kind, target))
+    }
+
+    fn load(&self, reference: &str) -> Result<String, Box<dyn Error>> {
+        let (kind, target) = Self::parse(reference)?;
+        let service = self.connect()?;
+        let items = service.search_items(Self::attributes(target, kind))?;
+        let item = items
+            .unlocked
+            .first()
+            .or(items.locked.first())
+            .ok_or_else(|| format!("No keyring item for {} {}", kind, target))?;
+        item.ensure_unlocked()?;
+        Ok(String::from_utf8(item.get_secret()?)?)
+    }
+
+    fn delete(&self, reference: &str) -> Result<(), Box<dyn Error>> {
+        let (kind, target) = Self::parse(reference)?;
+        let service = self.connect()?;
+        let items = service.search_items(Self::attributes(target, kind))?;
+        for item in items.unlocked.iter().chain(&items.locked) {
+            item.delete()?;
+        }
+        Ok(())
+    }
+}
+
+/// The store new secrets go to, as chosen by `store` in the config
+pub fn for_config(config: &Config) -> Box<dyn CredentialStore> {
+    match config.store {
+        StoreKind::File => Box::new(FileStore),
+        StoreKind::Keyring => Box::new(KeyringStore::new(None)),
+    }
+}
+
+/// The store holding `reference`, whichever was configured when it was saved
+pub fn holding(reference: &str) -> Box<dyn CredentialStore> {
+    if reference.starts_with(KEYRING_PREFIX) {
+        Box::new(KeyringStore::new(None))
+    } else {
+        Box::new(FileStore)
+    }
+}
+
+/// Resolves a saved `secret` or `passphrase` reference to the secret itself
+pub fn load_secret(reference: &str) -> Result<String, Box<dyn Error>> {
+    holding(reference).load(reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::{Arc, Mutex},
+    };
+    use zbus::{
+        ObjectServer, interface,
+        zvariant::{OwnedObjectPath, OwnedValue},
+    };
+
+    const COLLECTION: &str = "/org/freedesktop/secrets/collection/login";
+
+    type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+    /// Items of the mock keyring: path, attributes and secret
+    type Items = Arc<Mutex<Vec<(String, HashMap<String, String>, Vec<u8>)>>>;
+
+    fn path(path: &str) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(path.to_string()).unwrap()
+    }
+
+    fn find(items: &Items, attributes: &HashMap<String, String>) -> Vec<OwnedObjectPath> {
+        let items = items.lock().unwrap();
+        items
+            .iter()
+            .filter(|(_, item, _)| attributes.iter().all(|(k, v)| item.get(k) == Some(v)))
+            .map(|(item_path, _, _)| path(item_path))
+            .collect()
+    }
+
+    /// Just enough of the Secret Service API for `KeyringStore`, with plain transfers only
+    struct MockService(Items);
+
+    #[interface(name = "org.freedesktop.Secret.Service")]
+    impl MockService {
+        fn open_session(
+            &self,
+            algorithm: String,
+            _input: OwnedValue,
+        ) -> zbus::fdo::Result<(OwnedValue, OwnedObjectPath)> {
+            if algorithm != "plain" {
+                return Err(zbus::fdo::Error::NotSupported(algorithm));
+            }
+            let output = OwnedValue::try_from(zbus::zvariant::Value::from("")).unwrap();
+            Ok((output, path("/org/freedesktop/secrets/session/1")))
+        }
+
+        fn search_items(
+            &self,
+            attributes: HashMap<String, String>,
+        ) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+            (find(&self.0, &attributes), Vec::new())
+        }
+
+        fn read_alias(&self, _name: String) -> OwnedObjectPath {
+            path(COLLECTION)
+        }
+    }
+
+    struct MockCollection(Items);
+
+    #[interface(name = "org.freedesktop.Secret.Collection")]
+    impl MockCollection {
+        async fn create_item(
+            &self,
+            properties: HashMap<String, OwnedValue>,
+            secret: Secret,
+            replace: bool,
+            #[zbus(object_server)] server: &ObjectServer,
+        ) -> zbus::fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+            let attributes: HashMap<String, String> = properties
+                .get("org.freedesktop.Secret.Item.Attributes")
+                .map(|value| value.try_clone().unwrap().try_into().unwrap())
+                .unwrap_or_default();
+            let item_path = {
+                let mut items = self.0.lock().unwrap();
+                match items.iter_mut().find(|(_, item, _)| *item == attributes) {
+                    Some(existing) if replace => {
+                        existing.2 = secret.2;
+                        return Ok((path(&existing.0), path("/")));
+                    }
+                    _ => {
+                        let item_path = format!("{}/{}", COLLECTION, items.len() + 1);
+                        items.push((item_path.clone(), attributes, secret.2));
+                        item_path
+                    }
+                }
+            };
+            let item = MockItem(self.0.clone(), item_path.clone());
+            server.at(item_path.as_str(), item).await?;
+            Ok((path(&item_path), path("/")))
+        }
+
+        #[zbus(property)]
+        fn locked(&self) -> bool {
+            false
+        }
+    }
+
+    struct MockItem(Items, String);
+
+    #[interface(name = "org.freedesktop.Secret.Item")]
+    impl MockItem {
+        fn get_secret(&self, session: OwnedObjectPath) -> zbus::fdo::Result<Secret> {
+            let items = self.0.lock().unwrap();
+            let (_, _, secret) = items
+                .iter()
+                .find(|(item_path, _, _)| *item_path == self.1)
+                .ok_or_else(|| zbus::fdo::Error::UnknownObject(self.1.clone()))?;
+            Ok((session, Vec::new(), secret.clone(), "text/plain".into()))
+        }
+
+        fn delete(&self) -> OwnedObjectPath {
+            self.0
+                .lock()
+                .unwrap()
+                .retain(|(item_path, _, _)| *item_path != self.1);
+            path("/")
+        }
+
+        #[zbus(property)]
+        fn locked(&self) -> bool {
+            false
+        }
+    }
+
+    /// Private session bus, shut down when dropped
+    struct Bus(Child);
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            self.0.kill().ok();
+            self.0.wait().ok();
+        }
+    }
+
+    /// Starts a bus of our own and returns its address
+    fn start_bus() -> (Bus, String) {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("could not start dbus-daemon");
+        let stdout = child.stdout.take().unwrap();
+        let bus = Bus(child);
+        let mut address = String::new();
+        BufReader::new(stdout).read_line(&mut address).unwrap();
+        (bus, address.trim().to_string())
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn keyring_store_round_trips() {
+        let (_bus, address) = start_bus();
+        let items = Items::default();
+        let _service = zbus::blocking::connection::Builder::address(address.as_str())
+            .unwrap()
+            .name("org.freedesktop.secrets")
+            .unwrap()
+            .serve_at("/org/freedesktop/secrets", MockService(items.clone()))
+            .unwrap()
+            .serve_at(COLLECTION, MockCollection(items.clone()))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let store = KeyringStore::new(Some(address));
+        let reference = store
+            .store("alice@example.com:22", "password", "first")
+            .unwrap();
+        assert_eq!(reference, "keyring:password:alice@example.com:22");
+        assert_eq!(store.load(&reference).unwrap(), "first");
+
+        // Saving again replaces the item instead of adding a second one
+        store
+            .store("alice@example.com:22", "password", "second")
+            .unwrap();
+        assert_eq!(store.load(&reference).unwrap(), "second");
+        assert_eq!(items.lock().unwrap().len(), 1);
+
+        let other = store
+            .store("bob@example.com:22", "passphrase", "third")
+            .unwrap();
+        assert_eq!(holding(&other).name(), "keyring");
+        store.delete(&reference).unwrap();
+        assert!(store.load(&reference).is_err());
+        assert_eq!(store.load(&other).unwrap(), "third");
+    }
+
+    #[test]
+    fn keyring_store_rejects_bad_references() {
+        let store = KeyringStore::new(None);
+        assert!(store.load("keyring:no-kind").is_err());
+        assert!(store.delete("plain ciphertext").is_err());
+    }
+
+    #[test]
+    fn file_store_round_trips() {
+        config::unlock_test_vault("correct horse").unwrap();
+        let reference = FileStore
+            .store("alice@example.com:22", "password", "s3cret")
+            .unwrap();
+        assert_ne!(reference, "s3cret");
+        assert_eq!(holding(&reference).name(), "file");
+        assert_eq!(load_secret(&reference).unwrap(), "s3cret");
+        FileStore.delete(&reference).unwrap();
+        assert!(FileStore.load("bm90IGEgc2VjcmV0IGF0IGFsbA==").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! `trump creds`: manage the saved credentials outside of a connection.
+
+use crate::cli::CredsAction;
+use crate::config::{self, AuthData, AuthType, StoreKind};
+use crate::credential_store;
+use colored::*;
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::Path,
+};
+
+pub fn run(action: CredsAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        CredsAction::List => list(),
+        CredsAction::Add {
+            target,
+            key,
+            access_key,
+        } => add(&target, key.as_deref(), access_key),
+        CredsAction::Remove { target } => remove(&target),
+        CredsAction::Store { kind } => choose_store(kind),
+        CredsAction::RotateKey => {
+            let mut config = config::load_config()?;
+            let rotated = config::rotate_key(&mut config)?;
+            println!(
+                "{}",
+                format!("✔ Re-encrypted {} secret(s) under the new key.", rotated)
+                    .green()
+                    .bold()
+            );
+            Ok(())
+        }
+        CredsAction::Export { file } => {
+            let config = config::load_config()?;
+            let exported = config::export_credentials(&config, &file)?;
+            println!(
+                "{}",
+                format!(
+                    "✔ Exported {} credential(s) to {}.",
+                    exported,
+                    file.display()
+                )
+                .green()
+                .bold()
+            );
+            Ok(())
+        }
+        CredsAction::Import { file } => {
+            let config = config::load_config()?;
+            let imported = config::import_credentials(config, &file)?;
+            println!(
+                "{}",
+                format!("✔ Imported {} credential(s).", imported)
+                    .green()
+                    .bold()
+            );
+            Ok(())
+        }
+    }
+}
+
+fn list() -> Result<(), Box<dyn Error>> {
+    let config = config::load_config()?;
+    if config.targets.is_empty() {
+        println!("{}", "No saved credentials.".dimmed());
+        return Ok(());
+    }
+
+    let mut targets: Vec<(&String, &AuthData)> = config.targets.iter().collect();
+    targets.sort_by_key(|(config_key, _)| *config_key);
+    let width = targets.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (config_key, auth) in targets {
+        let detail = match auth.auth_type {
+            AuthType::KeyPath => match &auth.passphrase {
+                Some(passphrase) => format!(
+                    "{} (passphrase in {})",
+                    auth.secret,
+                    credential_store::holding(passphrase).name()
+                ),
+                None => auth.secret.clone(),
+            },
+            _ => format!("in {}", credential_store::holding(&auth.secret).name()),
+        };
+        println!(
+            "{:<width$}  {:<10}  {}",
+            config_key.green(),
+            auth.auth_type.secret_kind(),
+            detail.dimmed(),
+        );
+    }
+    Ok(())
+}
+
+fn add(target: &str, key: Option<&Path>, access_key: bool) -> Result<(), Box<dyn Error>> {
+    if !config::is_config_key(target) {
+        eprintln!("{}", "Misformatted USER@HOSTNAME:PORT!".red().bold());
+        return Err("Incorrect target address formatting!".into());
+    }
+
+    let config = config::load_config()?;
+    let store = credential_store::for_config(&config);
+    let auth = if let Some(key) = key {
+        let path = key
+            .canonicalize()
+            .map_err(|e| format!("Could not find {}: {}", key.display(), e))?;
+        let passphrase = rpassword::prompt_password("Key passphrase (empty for none): ")?;
+        let passphrase = if passphrase.is_empty() {
+            None
+        } else {
+            Some(store.store(target, "passphrase", &passphrase)?)
+        };
+        AuthData {
+            auth_type: AuthType::KeyPath,
+            secret: path.to_string_lossy().to_string(),
+            passphrase,
+        }
+    } else if access_key {
+        print!("Access key ID: ");
+        io::stdout().flush()?;
+        let mut id = String::new();
+        io::stdin().read_line(&mut id)?;
+        let secret = rpassword::prompt_password("Secret access key: ")?;
+        let pair = format!("{}:{}", id.trim(), secret);
+        AuthData {
+            auth_type: AuthType::AccessKey,
+            secret: store.store(target, AuthType::AccessKey.secret_kind(), &pair)?,
+            passphrase: None,
+        }
+    } else {
+        let password = rpassword::prompt_password("Password: ")?;
+        AuthData {
+            auth_type: AuthType::Password,
+            secret: store.store(target, AuthType::Password.secret_kind(), &password)?,
+            passphrase: None,
+        }
+    };
+    config::save_entry(config, target.to_string(), auth)
+}
+
+fn choose_store(kind: Option<StoreKind>) -> Result<(), Box<dyn Error>> {
+    let mut config = config::load_config()?;
+    let Some(kind) = kind else {
+        println!(
+            "New secrets go to the {} store.",
+            credential_store::for_config(&config).name()
+        );
+        return Ok(());
+    };
+
+    if kind == StoreKind::Keyring {
+        credential_store::KeyringStore::new(None).check()?;
+    }
+    config.store = kind;
+    config::save_config(&config)?;
+    println!(
+        "{}",
+        format!(
+            "✔ New secrets go to the {} store, saved ones stay where they are.",
+            credential_store::for_config(&config).name()
+        )
+        .green()
+        .bold()
+    );
+    Ok(())
+}
+
+fn remove(target: &str) -> Result<(), Box<dyn Error>> {
+    let mut config = config::load_config()?;
+    let auth = config
+        .targets
+        .remove(target)
+        .ok_or_else(|| format!("No saved credentials for {}!", target))?;
+    config::save_config(&config)?;
+    println!(
+        "{}",
+        format!("✔ Removed the credentials of {}.", target)
+            .green()
+            .bold()
+    );
+    // The entry is gone either way, an unreachable store only leaves its secret behind
+    for reference in auth.secret_references() {
+        let store = credential_store::holding(reference);
+        if let Err(e) = store.delete(reference) {
+            eprintln!(
+                "{} {}: {}",
+                "Warning: Could not delete the secret in the".yellow(),
+                store.name(),
+                e
+            );
+        }
+    }
+    Ok(())
+}
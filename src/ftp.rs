@@ -0,0 +1,640 @@
+use crate::backend::{
+    BackendResult, DirEntry, FileStat, RemoteBackend, RemoteWrite, normalize_path,
+};
+use crate::config;
+use crate::credential_store;
+use chrono::NaiveDateTime;
+use colored::*;
+use openssl::ssl::{SslConnector, SslMethod, SslSession, SslStream, SslVerifyMode};
+use std::{
+    error::Error,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpStream},
+    path::{Path, PathBuf},
+};
+
+/// Control or data connection, optionally wrapped in TLS after `AUTH TLS`
+enum FtpStream {
+    Plain(TcpStream),
+    Tls(Box<SslStream<TcpStream>>),
+}
+
+impl FtpStream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            FtpStream::Plain(tcp) => tcp,
+            FtpStream::Tls(ssl) => ssl.get_ref(),
+        }
+    }
+}
+
+impl Read for FtpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            FtpStream::Plain(tcp) => tcp.read(buf),
+            FtpStream::Tls(ssl) => ssl.read(buf),
+        }
+    }
+}
+
+impl Write for FtpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FtpStream::Plain(tcp) => tcp.write(buf),
+            FtpStream::Tls(ssl) => ssl.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FtpStream::Plain(tcp) => tcp.flush(),
+            FtpStream::Tls(ssl) => ssl.flush(),
+        }
+    }
+}
+
+pub struct FtpClient {
+    control: BufReader<FtpStream>,
+    /// Set once `PROT P` is accepted, data connections are then wrapped in TLS too
+    tls: Option<SslConnector>,
+    mlsd: bool,
+    pub host_name: String,
+    pub user: String,
+    pub port: String,
+    pub current_directory: PathBuf,
+    pub home_directory: PathBuf,
+}
+
+pub fn ftp_connect(
+    user: &str,
+    hostname: &str,
+    port: Option<&str>,
+    tls: bool,
+    insecure: bool,
+) -> Result<FtpClient, Box<dyn Error>> {
+    let port = port.unwrap_or("21");
+
+    let config = config::load_config().unwrap_or_else(|e| {
+        eprintln!("{} {}.", "Warning: Could not load config:".yellow(), e);
+        config::Config::default()
+    });
+    let config_key = format!("{}@{}:{}", user, hostname, port);
+
+    println!("{} {}:{}.", "Connecting to".cyan(), hostname, port);
+    let tcp = TcpStream::connect(format!("{hostname}:{port}"))?;
+    tcp.set_nodelay(true)?;
+
+    let mut client = FtpClient {
+        control: BufReader::new(FtpStream::Plain(tcp)),
+        tls: None,
+        mlsd: false,
+        host_name: hostname.to_string(),
+        user: user.to_string(),
+        port: port.to_string(),
+        current_directory: PathBuf::new(),
+        home_directory: PathBuf::new(),
+    };
+
+    let (_, banner) = client.expect_reply(&[220])?;
+    println!("{} {}", "Server Banner:".dimmed(), banner.trim());
+
+    if tls {
+        client.start_tls(hostname, insecure)?;
+        println!(
+            "{}",
+            "✔ Control connection secured with TLS.".green().bold()
+        );
+    }
+
+    let mut authenticated = false;
+    if let Some(auth_data) = config.targets.get(&config_key)
+        && let config::AuthType::Password = auth_data.auth_type
+    {
+        println!("{}", "Found saved password. Attempting auto-login.".cyan());
+        match credential_store::load_secret(&auth_data.secret) {
+            Ok(password) => {
+                if client.login(user, &password).is_ok() {
+                    println!("{}", "✔ Auto-login successful.".green().bold());
+                    authenticated = true;
+                } else {
+                    println!("{}", "✖ Saved password failed!".red());
+                }
+            }
+            Err(e) => eprintln!("{} {}!", "Failed to decrypt saved password:".red(), e),
+        }
+    }
+
+    if !authenticated {
+        println!("{}", "Falling back to interactive password.".yellow());
+        let password = rpassword::prompt_password("Password: ")?;
+        if let Err(e) = client.login(user, &password) {
+            println!("Password auth failed: {}.", e);
+            return Err(format!(
+                "{}",
+                "Authentication failed. Please check your credentials!"
+                    .red()
+                    .bold()
+            )
+            .into());
+        }
+        println!("{}", "✔ Authenticated.".green().bold());
+
+        if !config.targets.contains_key(&config_key) {
+            config::offer_to_save_password(config, config_key, &password)?;
+        }
+    }
+
+    client.expect("TYPE I", &[200])?;
+    if let Ok((211, features)) = client.command("FEAT") {
+        // MLSD support is advertised through the MLST feature (RFC 3659)
+        client.mlsd = features
+            .lines()
+            .any(|line| line.trim_start().to_ascii_uppercase().starts_with("MLST"));
+    }
+
+    let home = client.pwd()?;
+    client.current_directory = home.clone();
+    client.home_directory = home;
+
+    Ok(client)
+}
+
+impl FtpClient {
+    /// Reads a (possibly multi-line) reply, returning its code and full text
+    fn read_reply(&mut self) -> BackendResult<(u32, String)> {
+        let mut text = String::new();
+        let mut line = String::new();
+        if self.control.read_line(&mut line)? == 0 {
+            return Err("FTP control connection closed!".into());
+        }
+        let code: u32 = line
+            .get(..3)
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| format!("Malformed FTP reply: {}", line.trim()))?;
+        text.push_str(&line);
+
+        if line.as_bytes().get(3) == Some(&b'-') {
+            let terminator = format!("{} ", code);
+            loop {
+                line.clear();
+                if self.control.read_line(&mut line)? == 0 {
+                    return Err("FTP control connection closed!".into());
+                }
+                text.push_str(&line);
+                if line.starts_with(&terminator) {
+                    break;
+                }
+            }
+        }
+
+        Ok((code, text))
+    }
+
+    fn command(&mut self, cmd: &str) -> BackendResult<(u32, String)> {
+        let stream = self.control.get_mut();
+        stream.write_all(format!("{}\r\n", cmd).as_bytes())?;
+        stream.flush()?;
+        self.read_reply()
+    }
+
+    fn expect_reply(&mut self, codes: &[u32]) -> BackendResult<(u32, String)> {
+        let (code, text) = self.read_reply()?;
+        if codes.contains(&code) {
+            Ok((code, text))
+        } else {
+            Err(format!("Unexpected FTP reply: {}", text.trim()).into())
+        }
+    }
+
+    fn expect(&mut self, cmd: &str, codes: &[u32]) -> BackendResult<String> {
+        let (code, text) = self.command(cmd)?;
+        if codes.contains(&code) {
+            Ok(text)
+        } else {
+            let verb = cmd.split_whitespace().next().unwrap_or(cmd);
+            Err(format!("FTP {} failed: {}", verb, text.trim()).into())
+        }
+    }
+
+    fn start_tls(&mut self, hostname: &str, insecure: bool) -> BackendResult<()> {
+        self.expect("AUTH TLS", &[234])?;
+
+        let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+        if insecure {
+            builder.set_verify(SslVerifyMode::NONE);
+        }
+        let connector = builder.build();
+
+        let tcp = self.control.get_ref().tcp().try_clone()?;
+        let ssl = connector.connect(hostname, tcp)?;
+        self.control = BufReader::new(FtpStream::Tls(Box::new(ssl)));
+
+        self.expect("PBSZ 0", &[200])?;
+        self.expect("PROT P", &[200])?;
+        self.tls = Some(connector);
+        Ok(())
+    }
+
+    fn login(&mut self, user: &str, password: &str) -> BackendResult<()> {
+        let (code, text) = self.command(&format!("USER {}", user))?;
+        match code {
+            230 => Ok(()),
+            331 | 332 => self
+                .expect(&format!("PASS {}", password), &[230, 202])
+                .map(|_| ()),
+            _ => Err(format!("FTP USER failed: {}", text.trim()).into()),
+        }
+    }
+
+    fn pwd(&mut self) -> BackendResult<PathBuf> {
+        let text = self.expect("PWD", &[257])?;
+        let start = text.find('"').ok_or("Malformed PWD reply!")?;
+        let end = text
+            .rfind('"')
+            .filter(|end| *end > start)
+            .ok_or("Malformed PWD reply!")?;
+        Ok(PathBuf::from(text[start + 1..end].replace("\"\"", "\"")))
+    }
+
+    /// Changes into `path` to see whether it is a directory and where it resolves to, then
+    /// back, so the server's working directory stays where it was
+    fn probe_dir(&mut self, path: &Path) -> BackendResult<Option<PathBuf>> {
+        let previous = self.pwd()?;
+        if self
+            .expect(&format!("CWD {}", path.display()), &[250])
+            .is_err()
+        {
+            return Ok(None);
+        }
+        let resolved = self.pwd();
+        self.expect(&format!("CWD {}", previous.display()), &[250])?;
+        resolved.map(Some)
+    }
+
+    /// Opens a passive data connection and issues `cmd` over it
+    fn open_transfer(&mut self, cmd: &str) -> BackendResult<FtpTransfer<'_>> {
+        let text = self.expect("PASV", &[227])?;
+        let numbers: Vec<u16> = text
+            .split(['(', ')'])
+            .nth(1)
+            .ok_or("Malformed PASV reply!")?
+            .split(',')
+            .map(|n| n.trim().parse())
+            .collect::<Result<_, _>>()?;
+        if numbers.len() != 6 {
+            return Err("Malformed PASV reply!".into());
+        }
+        let data_port = numbers[4] * 256 + numbers[5];
+
+        // Connect to the control peer rather than the advertised address, which is
+        // frequently a private address behind NAT
+        let peer: IpAddr = self.control.get_ref().tcp().peer_addr()?.ip();
+        let tcp = TcpStream::connect((peer, data_port))?;
+
+        self.expect(cmd, &[125, 150])?;
+
+        let data = match &self.tls {
+            Some(connector) => {
+                let session: Option<SslSession> = match self.control.get_ref() {
+                    FtpStream::Tls(ssl) => ssl.ssl().session().map(|s| s.to_owned()),
+                    FtpStream::Plain(_) => None,
+                };
+                let mut config = connector.configure()?;
+                if let Some(session) = &session {
+                    // Servers like vsftpd require the data channel to resume the control session
+                    // SAFETY: the session comes from the control connection, which was set
+                    // up by this same connector and so shares its SslContext
+                    unsafe { config.set_session(session)? };
+                }
+                FtpStream::Tls(Box::new(config.connect(&self.host_name, tcp)?))
+            }
+            None => FtpStream::Plain(tcp),
+        };
+
+        Ok(FtpTransfer {
+            client: self,
+            data: Some(data),
+        })
+    }
+
+    fn list_raw(&mut self, path: &Path) -> BackendResult<String> {
+        let cmd = if self.mlsd {
+            format!("MLSD {}", path.display())
+        } else {
+            format!("LIST -a {}", path.display())
+        };
+        let mut listing = String::new();
+        let mut transfer = self.open_transfer(&cmd)?;
+        transfer.read_to_string(&mut listing)?;
+        transfer.complete()?;
+        Ok(listing)
+    }
+}
+
+/// Data connection that reads the transfer completion reply when finished
+struct FtpTransfer<'a> {
+    client: &'a mut FtpClient,
+    data: Option<FtpStream>,
+}
+
+impl FtpTransfer<'_> {
+    /// Closes the data connection and waits for the server to confirm the transfer
+    fn complete(&mut self) -> BackendResult<()> {
+        let Some(mut data) = self.data.take() else {
+            return Ok(());
+        };
+        if let FtpStream::Tls(ssl) = &mut data {
+            ssl.shutdown().ok();
+        }
+        // The server only completes the transfer once the data connection is closed
+        drop(data);
+        self.client.expect_reply(&[226, 250])?;
+        Ok(())
+    }
+}
+
+impl Read for FtpTransfer<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.data {
+            Some(data) => data.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+impl Write for FtpTransfer<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.data {
+            Some(data) => data.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.data {
+            Some(data) => data.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl RemoteWrite for FtpTransfer<'_> {
+    fn finish(mut self: Box<Self>) -> BackendResult<()> {
+        self.complete()
+    }
+}
+
+impl Drop for FtpTransfer<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.complete() {
+            eprintln!("{} {}", "FTP transfer error:".red(), e);
+        }
+    }
+}
+
+/// Parses one `MLSD` line (`fact=value;...; name`)
+fn parse_mlsd_line(dir: &Path, line: &str) -> Option<DirEntry> {
+    let (facts, name) = line.split_once(' ')?;
+    let mut stat = FileStat::default();
+
+    for fact in facts.split(';').filter(|f| !f.is_empty()) {
+        let (key, value) = fact.split_once('=')?;
+        match key.to_ascii_lowercase().as_str() {
+            "type" => match value.to_ascii_lowercase().as_str() {
+                "cdir" | "pdir" => return None,
+                "dir" => stat.is_dir = true,
+                _ => {}
+            },
+            "size" => stat.size = value.parse().ok(),
+            "modify" => {
+                stat.mtime = value
+                    .get(..14)
+                    .and_then(|time| NaiveDateTime::parse_from_str(time, "%Y%m%d%H%M%S").ok())
+                    .map(|t| t.and_utc().timestamp() as u64);
+            }
+            "unix.mode" => stat.perm = u32::from_str_radix(value, 8).ok(),
+            "unix.uid" | "unix.owner" => stat.uid = value.parse().ok(),
+            "unix.gid" | "unix.group" => stat.gid = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    if let Some(perm) = stat.perm {
+        stat.perm = Some(perm | if stat.is_dir { 0o040000 } else { 0o100000 });
+    }
+
+    Some(DirEntry {
+        path: dir.join(name),
+        stat,
+    })
+}
+
+/// Parses one Unix style `LIST` line (`drwxr-xr-x 2 user group 4096 Jan 1 12:00 name`)
+fn parse_list_line(dir: &Path, line: &str) -> Option<DirEntry> {
+    let mut rest = line.trim_end();
+    let mut fields = Vec::with_capacity(8);
+    for _ in 0..8 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    let name = rest.trim_start();
+    let name = name.split_once(" -> ").map_or(name, |(n, _)| n);
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+
+    let mode_str = fields[0].as_bytes();
+    if mode_str.len() < 10 {
+        return None;
+    }
+    let mut perm = match mode_str[0] {
+        b'd' => 0o040000,
+        b'l' => 0o120000,
+        _ => 0o100000,
+    };
+    for (i, bit) in mode_str[1..10].iter().enumerate() {
+        if *bit != b'-' && *bit != b'S' && *bit != b'T' {
+            perm |= 1 << (8 - i);
+        }
+    }
+
+    Some(DirEntry {
+        path: dir.join(name),
+        stat: FileStat {
+            is_dir: mode_str[0] == b'd',
+            size: fields[4].parse().ok(),
+            perm: Some(perm),
+            ..Default::default()
+        },
+    })
+}
+
+impl RemoteBackend for FtpClient {
+    fn label(&self) -> String {
+        format!("ftp:{}@{}:{}", self.user, self.host_name, self.port)
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn can_exec(&self) -> bool {
+        false
+    }
+
+    fn realpath(&mut self, path: &Path) -> BackendResult<PathBuf> {
+        let expanded = match path.strip_prefix("~") {
+            Ok(rest) => self.home_directory.join(rest),
+            Err(_) => path.to_path_buf(),
+        };
+        match self.probe_dir(&expanded)? {
+            Some(resolved) => Ok(resolved),
+            None => Ok(normalize_path(&expanded)),
+        }
+    }
+
+    fn exec_stream(
+        &mut self,
+        _cmd: &str,
+        _stdin: Option<&mut dyn Read>,
+        _stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        Err("Shell commands are not available over ftp!".into())
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        if self.probe_dir(path)?.is_some() {
+            return Ok(FileStat {
+                is_dir: true,
+                ..Default::default()
+            });
+        }
+
+        let text = self.expect(&format!("SIZE {}", path.display()), &[213])?;
+        Ok(FileStat {
+            is_dir: false,
+            size: text.get(4..).and_then(|s| s.trim().parse().ok()),
+            ..Default::default()
+        })
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        let listing = self.list_raw(path)?;
+        let parse = if self.mlsd {
+            parse_mlsd_line
+        } else {
+            parse_list_line
+        };
+        Ok(listing
+            .lines()
+            .filter_map(|line| parse(path, line))
+            .collect())
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        Ok(Box::new(
+            self.open_transfer(&format!("RETR {}", path.display()))?,
+        ))
+    }
+
+    fn open_write(&mut self, path: &Path, _size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        Ok(Box::new(
+            self.open_transfer(&format!("STOR {}", path.display()))?,
+        ))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        self.expect(&format!("RNFR {}", from.display()), &[350])?;
+        self.expect(&format!("RNTO {}", to.display()), &[250])?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        if self.stat(path)?.is_dir {
+            self.expect(&format!("RMD {}", path.display()), &[250])?;
+        } else {
+            self.expect(&format!("DELE {}", path.display()), &[250])?;
+        }
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        self.expect(&format!("MKD {}", path.display()), &[257])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mlsd_line_reads_facts() {
+        let entry = parse_mlsd_line(
+            Path::new("/pub"),
+            "type=file;size=1234;modify=20240102030405.123;UNIX.mode=0644;UNIX.uid=1000; notes.txt",
+        )
+        .unwrap();
+        assert_eq!(entry.path, Path::new("/pub/notes.txt"));
+        assert!(!entry.stat.is_dir);
+        assert_eq!(entry.stat.size, Some(1234));
+        assert_eq!(entry.stat.mtime, Some(1704164645));
+        assert_eq!(entry.stat.perm, Some(0o100644));
+        assert_eq!(entry.stat.uid, Some(1000));
+    }
+
+    #[test]
+    fn parse_mlsd_line_handles_dirs_and_odd_lines() {
+        let entry = parse_mlsd_line(Path::new("/"), "type=dir;unix.mode=755; my dir").unwrap();
+        assert_eq!(entry.path, Path::new("/my dir"));
+        assert!(entry.stat.is_dir);
+        assert_eq!(entry.stat.perm, Some(0o040755));
+
+        assert!(parse_mlsd_line(Path::new("/"), "type=cdir; .").is_none());
+        assert!(parse_mlsd_line(Path::new("/"), "type=pdir; ..").is_none());
+        assert!(parse_mlsd_line(Path::new("/"), "no-facts-here").is_none());
+
+        // A short or multi-byte modify value must not panic
+        let entry = parse_mlsd_line(Path::new("/"), "type=file;modify=2024010203040é; x").unwrap();
+        assert_eq!(entry.stat.mtime, None);
+    }
+
+    #[test]
+    fn parse_list_line_reads_unix_listing() {
+        let entry = parse_list_line(
+            Path::new("/srv"),
+            "-rwxr-x--- 1 alice staff 4096 Jan  1 12:00 run script.sh",
+        )
+        .unwrap();
+        assert_eq!(entry.path, Path::new("/srv/run script.sh"));
+        assert!(!entry.stat.is_dir);
+        assert_eq!(entry.stat.size, Some(4096));
+        assert_eq!(entry.stat.perm, Some(0o100750));
+
+        let entry =
+            parse_list_line(Path::new("/"), "drwxr-xr-x 2 root root 512 Mar 3 2023 etc").unwrap();
+        assert!(entry.stat.is_dir);
+        assert_eq!(entry.stat.perm, Some(0o040755));
+    }
+
+    #[test]
+    fn parse_list_line_handles_links_and_skips() {
+        let entry = parse_list_line(
+            Path::new("/"),
+            "lrwxrwxrwx 1 root root 7 Jan 1 12:00 latest -> v1.2.3",
+        )
+        .unwrap();
+        assert_eq!(entry.path, Path::new("/latest"));
+        assert_eq!(entry.stat.perm, Some(0o120777));
+
+        assert!(parse_list_line(Path::new("/"), "drwxr-xr-x 2 u g 0 Jan 1 12:00 .").is_none());
+        assert!(parse_list_line(Path::new("/"), "drwxr-xr-x 2 u g 0 Jan 1 12:00 ..").is_none());
+        assert!(parse_list_line(Path::new("/"), "total 12").is_none());
+    }
+}
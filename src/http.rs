@@ -0,0 +1,77 @@
+//! HTTP pieces shared by the WebDAV and S3 backends.
+
+use crate::backend::{BackendResult, RemoteWrite};
+use std::{
+    error::Error,
+    io::{self, Write},
+    thread::{self, JoinHandle},
+};
+use ureq::Request;
+
+/// Request body of a streaming PUT, the upload runs on its own thread
+pub struct PutWriter {
+    pipe: Option<io::PipeWriter>,
+    upload: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl PutWriter {
+    /// Starts sending `request`, with everything written to the writer as its body
+    pub fn start(
+        request: Request,
+        on_error: fn(ureq::Error) -> Box<dyn Error>,
+    ) -> io::Result<Self> {
+        let (reader, writer) = io::pipe()?;
+        let upload = thread::spawn(move || {
+            request
+                .send(reader)
+                .map(|_| ())
+                .map_err(|e| on_error(e).to_string())
+        });
+        Ok(PutWriter {
+            pipe: Some(writer),
+            upload: Some(upload),
+        })
+    }
+}
+
+impl Write for PutWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.pipe {
+            Some(pipe) => pipe.write(buf),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.pipe {
+            Some(pipe) => pipe.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PutWriter {
+    /// Ends the body and waits for the server's reply to the upload
+    fn complete(&mut self) -> Result<(), String> {
+        drop(self.pipe.take());
+        match self.upload.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err("upload thread panicked".to_string()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl RemoteWrite for PutWriter {
+    fn finish(mut self: Box<Self>) -> BackendResult<()> {
+        self.complete()
+            .map_err(|e| format!("Remote write failed: {}", e).into())
+    }
+}
+
+impl Drop for PutWriter {
+    fn drop(&mut self) {
+        // An abandoned upload still has to release its thread
+        self.complete().ok();
+    }
+}
@@ -0,0 +1,191 @@
+use crate::backend::{BackendResult, DirEntry, FileStat, RemoteBackend, RemoteWrite};
+use std::{
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::UNIX_EPOCH,
+};
+
+/// Directory on this machine, driven through the same REPL as a remote host.
+/// Commands run through the local `sh`, so it behaves like an SSH target.
+pub struct LocalClient {
+    pub current_directory: PathBuf,
+}
+
+pub fn local_connect(path: Option<PathBuf>) -> Result<LocalClient, Box<dyn Error>> {
+    let path = match path {
+        Some(path) => path,
+        None => std::env::current_dir()?,
+    };
+    let current_directory = path.canonicalize()?;
+    if !current_directory.is_dir() {
+        return Err(format!("{} is not a directory!", current_directory.display()).into());
+    }
+
+    Ok(LocalClient { current_directory })
+}
+
+/// Runs a local process, streaming `stdin` into it and its stdout into `stdout`.
+/// Returns the captured stderr and exit status, like `RemoteBackend::exec_stream`.
+pub fn stream_command(
+    command: &mut Command,
+    stdin: Option<&mut dyn Read>,
+    stdout: &mut dyn Write,
+) -> BackendResult<(String, i32)> {
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut child_stdout = child.stdout.take().expect("Failed to take stdout");
+    let mut child_stderr = child.stderr.take().expect("Failed to take stderr");
+    let child_stdin = child.stdin.take();
+
+    // Drain stderr (and stdout while feeding stdin) on other threads so no pipe can
+    // fill up and block the child. The trait's reader and writer are not `Send`.
+    let stderr = thread::scope(|scope| -> io::Result<String> {
+        let stderr_reader = scope.spawn(move || {
+            let mut stderr = String::new();
+            child_stderr.read_to_string(&mut stderr).map(|_| stderr)
+        });
+
+        match (stdin, child_stdin) {
+            (Some(input), Some(mut child_stdin)) => {
+                let stdout_reader = scope.spawn(move || {
+                    let mut buffer = Vec::new();
+                    child_stdout.read_to_end(&mut buffer).map(|_| buffer)
+                });
+                io::copy(input, &mut child_stdin)?;
+                drop(child_stdin);
+                let buffer = stdout_reader.join().expect("stdout reader panicked")?;
+                stdout.write_all(&buffer)?;
+            }
+            _ => {
+                io::copy(&mut child_stdout, stdout)?;
+            }
+        }
+
+        stderr_reader.join().expect("stderr reader panicked")
+    })?;
+
+    let status = child.wait()?;
+    Ok((stderr, status.code().unwrap_or(-1)))
+}
+
+impl From<&fs::Metadata> for FileStat {
+    fn from(metadata: &fs::Metadata) -> Self {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            FileStat {
+                is_dir: metadata.is_dir(),
+                size: Some(metadata.len()),
+                perm: Some(metadata.mode()),
+                mtime,
+                uid: Some(metadata.uid()),
+                gid: Some(metadata.gid()),
+            }
+        }
+
+        #[cfg(not(unix))]
+        FileStat {
+            is_dir: metadata.is_dir(),
+            size: Some(metadata.len()),
+            mtime,
+            ..Default::default()
+        }
+    }
+}
+
+impl RemoteBackend for LocalClient {
+    fn label(&self) -> String {
+        "local".to_string()
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn realpath(&mut self, path: &Path) -> BackendResult<PathBuf> {
+        let expanded = match (path.strip_prefix("~"), dirs::home_dir()) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => path.to_path_buf(),
+        };
+        Ok(expanded.canonicalize()?)
+    }
+
+    fn exec_stream(
+        &mut self,
+        cmd: &str,
+        stdin: Option<&mut dyn Read>,
+        stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(&self.current_directory);
+        stream_command(&mut command, stdin, stdout)
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        Ok(FileStat::from(&fs::metadata(path)?))
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        fs::read_dir(path)?
+            .map(|entry| {
+                let entry = entry?;
+                Ok(DirEntry {
+                    stat: FileStat::from(&entry.metadata()?),
+                    path: entry.path(),
+                })
+            })
+            .collect()
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn open_write(&mut self, path: &Path, _size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        fs::rename(from, to)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        if fs::metadata(path)?.is_dir() {
+            fs::remove_dir(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        fs::create_dir(path)?;
+        Ok(())
+    }
+}
@@ -1,258 +1,169 @@
+mod backend;
 mod cli;
 mod config;
+mod container;
+mod credential_store;
+mod creds;
+mod ftp;
+mod http;
+mod local;
 mod repl;
-
+mod s3;
+mod serial;
+mod sftp;
+mod shell;
+mod ssh;
+mod ssh_config;
+mod telnet;
+mod terminal;
+mod webdav;
+
+use backend::RemoteBackend;
 use clap::Parser;
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, SshArgs};
 use colored::*;
-use ssh2::{KeyboardInteractivePrompt, Prompt, Session};
-use std::{error::Error, io::Read, io::Write, net::TcpStream, path::PathBuf};
-
-struct SimplePasswordPrompter {
-    password: String,
-}
-
-impl KeyboardInteractivePrompt for SimplePasswordPrompter {
-    fn prompt<'a>(
-        &mut self,
-        _username: &str,
-        instructions: &str,
-        prompts: &[Prompt<'a>],
-    ) -> Vec<String> {
-        if !instructions.is_empty() {
-            println!("{} {}", "Instructions:".dimmed(), instructions);
-        }
-
-        prompts
-            .iter()
-            .map(|p| {
-                if !p.text.is_empty() {
-                    println!("{} {}", "Server Prompt:".dimmed(), p.text);
-                }
-
-                if p.echo {
-                    String::new()
-                } else {
-                    self.password.clone()
-                }
-            })
-            .collect()
-    }
-}
-
-pub struct SSHClient {
-    pub session: Session,
-    pub host_name: String,
-    pub user: String,
-    pub current_directory: PathBuf,
-    pub port: String,
-}
+use std::{error::Error, path::PathBuf};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
 
     match args.command {
-        Commands::Ssh { target, identity } => {
-            let user_hostname = target;
-            let user_hostname_vect: Vec<&str> = user_hostname.split("@").collect();
-            if user_hostname_vect.len() != 2 {
-                eprintln!("{}", "Misformatted USER@HOSTNAME[:PORT]!".red().bold());
-                return Err("Incorrect SSH address formatting!".into());
-            } else {
-                let user = user_hostname_vect[0];
-                let hostname_port: Vec<&str> = user_hostname_vect[1].split(":").collect();
-                let (hostname, port) = match hostname_port.len() {
-                    1 => (hostname_port[0], None),
-                    2 => (hostname_port[0], Some(hostname_port[1])),
-                    _ => {
-                        eprintln!("{}", "Misformatted USER@HOSTNAME:PORT!".red().bold());
-                        return Err("Incorrect SSH address formatting!".into());
-                    }
-                };
-                ssh_connect(user, hostname, port, identity)?;
+        Commands::Ssh { target, mut ssh } => {
+            let profile = config::load_profile(&target)?;
+            let target = match &profile {
+                Some(profile) => apply_profile(profile, &mut ssh),
+                None => target,
+            };
+            let target = ssh_config::resolve_target(&target, &ssh)?;
+            let mut client = ssh::ssh_connect(&target, &ssh)?;
+            if let Some(profile) = &profile {
+                start_profile(&mut client, profile)?;
             }
+            repl::repl(&mut client)?;
         }
-    }
-
-    Ok(())
-}
-
-fn ssh_connect(
-    user: &str,
-    hostname: &str,
-    port: Option<&str>,
-    identity: Option<PathBuf>,
-) -> Result<(), Box<dyn Error>> {
-    let port = port.unwrap_or("22");
-
-    let config = config::load_config().unwrap_or_else(|e| {
-        eprintln!("{} {}.", "Warning: Could not load config:".yellow(), e);
-        config::Config::default()
-    });
-    let config_key = format!("{}@{}:{}", user, hostname, port);
-
-    let mut session_opt: Option<Session> = None;
-
-    {
-        println!("{} {}:{}.", "Connecting to".cyan(), hostname, port);
-        let tcp = TcpStream::connect(format!("{hostname}:{port}"))?;
-        tcp.set_nodelay(true)?;
-        let mut session = Session::new()?;
-        session.set_tcp_stream(tcp);
-        session.set_blocking(true);
-        session.handshake()?;
-
-        if let Some(banner) = session.banner() {
-            println!("{} {}", "Server Banner:".dimmed(), banner.trim());
+        Commands::Sftp { target, ssh } => {
+            let target = ssh_config::resolve_target(&target, &ssh)?;
+            let mut client = sftp::sftp_connect(&target, &ssh)?;
+            repl::repl(&mut client)?;
         }
-
-        let _ = session.auth_methods(user);
-
-        let mut authenticated = session.authenticated();
-        if authenticated {
-            println!(
-                "{}",
-                "✔ Authenticated (No credentials required).".green().bold()
-            );
+        Commands::Ftp {
+            target,
+            tls,
+            insecure,
+        } => {
+            let (user, hostname, port) = parse_target(&target)?;
+            let mut client = ftp::ftp_connect(user, hostname, port, tls, insecure)?;
+            repl::repl(&mut client)?;
         }
-
-        if !authenticated && let Some(raw_path) = &identity {
-            let path = raw_path.canonicalize().unwrap_or(raw_path.clone());
-            println!("{} {:?}.", "Trying identity file:".blue(), path);
-            if session
-                .userauth_pubkey_file(user, None, &path, None)
-                .is_ok()
-            {
-                println!("{}", "✔ Authenticated with identity file.".green().bold());
-                authenticated = true;
-            } else {
-                println!("{}", "✖ Identity file authentication failed!".red());
-            }
+        Commands::Webdav { url, insecure } => {
+            let mut client = webdav::webdav_connect(&url, insecure)?;
+            repl::repl(&mut client)?;
         }
-
-        if !authenticated && let Some(auth_data) = config.targets.get(&config_key) {
-            match &auth_data.auth_type {
-                config::AuthType::Password => {
-                    println!("{}", "Found saved password. Attempting auto-login.".cyan());
-                    match config::decrypt(&auth_data.secret) {
-                        Ok(password) => {
-                            if session.userauth_password(user, &password).is_ok() {
-                                println!("{}", "✔ Auto-login successful.".green().bold());
-                                authenticated = true;
-                            } else {
-                                println!("{}", "✖ Saved password failed!".red());
-                            }
-                        }
-                        Err(e) => eprintln!("{} {}!", "Failed to decrypt saved password:".red(), e),
-                    }
-                }
-                config::AuthType::KeyPath => {
-                    let path = PathBuf::from(&auth_data.secret);
-                    println!("{} {:?}.", "Found saved identity key:".cyan(), path);
-                    if session
-                        .userauth_pubkey_file(user, None, &path, None)
-                        .is_ok()
-                    {
-                        println!("{}", "✔ Auto-login successful.".green().bold());
-                        authenticated = true;
-                    } else {
-                        println!("{}", "✖ Saved identity key failed!".red());
-                    }
-                }
-            }
+        Commands::S3 {
+            target,
+            endpoint,
+            region,
+            insecure,
+        } => {
+            let mut client = s3::s3_connect(&target, &endpoint, &region, insecure)?;
+            repl::repl(&mut client)?;
         }
-
-        if authenticated {
-            session_opt = Some(session);
+        Commands::Telnet { target } => {
+            let (hostname, port) = match target.split_once(':') {
+                Some((hostname, port)) => (hostname, Some(port)),
+                None => (target.as_str(), None),
+            };
+            let mut client = telnet::telnet_connect(hostname, port)?;
+            repl::repl(&mut client)?;
         }
-    }
-
-    let session = if let Some(s) = session_opt {
-        s
-    } else {
-        println!("{}", "Falling back to interactive password.".yellow());
-        let password = rpassword::prompt_password("Password: ")?;
-
-        println!("{} {}:{}.", "Reconnecting to".cyan(), hostname, port);
-        let tcp = TcpStream::connect(format!("{hostname}:{port}"))?;
-        tcp.set_nodelay(true)?;
-        let mut session = Session::new()?;
-        session.set_tcp_stream(tcp);
-        session.set_blocking(true);
-        session.handshake()?;
-
-        if let Some(banner) = session.banner() {
-            println!("{} {}", "Server Banner:".dimmed(), banner.trim());
+        Commands::Serial { device, baud } => {
+            let mut client = serial::serial_connect(&device, baud)?;
+            repl::repl(&mut client)?;
         }
-
-        let _ = session.auth_methods(user);
-
-        if let Err(e) = session.userauth_password(user, &password) {
-            println!("Password auth failed: {}.", e);
-            let mut prompter = SimplePasswordPrompter {
-                password: password.clone(),
-            };
-            if let Err(e_ki) = session.userauth_keyboard_interactive(user, &mut prompter) {
-                println!("Keyboard-interactive auth failed: {}.", e_ki);
-                return Err(format!(
-                    "{}",
-                    "Authentication failed. Please check your credentials!"
-                        .red()
-                        .bold()
-                )
-                .into());
-            }
+        Commands::Docker { container } => {
+            let mut client = container::container_connect("docker", &container)?;
+            repl::repl(&mut client)?;
         }
-
-        if !config.targets.contains_key(&config_key) {
-            print!(
-                "{} ",
-                "Do you want to save this password for auto-login? [y/N]"
-                    .yellow()
-                    .bold()
-            );
-            std::io::stdout().flush()?;
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
-            if input.trim().eq_ignore_ascii_case("y")
-                && let Ok(encrypted) = config::encrypt(&password)
-            {
-                let mut new_config = config;
-                new_config.targets.insert(
-                    config_key,
-                    config::AuthData {
-                        auth_type: config::AuthType::Password,
-                        secret: encrypted,
-                    },
-                );
-                config::save_config(&new_config)?;
-                println!("{}", "✔ Credentials saved.".green().bold());
-            }
+        Commands::Podman { container } => {
+            let mut client = container::container_connect("podman", &container)?;
+            repl::repl(&mut client)?;
         }
+        Commands::K8s { target } => {
+            let mut client = container::pod_connect(&target)?;
+            repl::repl(&mut client)?;
+        }
+        Commands::Local { path } => {
+            let mut client = local::local_connect(path)?;
+            repl::repl(&mut client)?;
+        }
+        Commands::Creds { action } => creds::run(action)?,
+    }
 
-        session
-    };
-
-    let mut pwd_channel = session.channel_session()?;
-
-    pwd_channel.exec("pwd -P")?;
+    Ok(())
+}
 
-    let mut raw_pwd = String::new();
-    pwd_channel.read_to_string(&mut raw_pwd)?;
-    pwd_channel.wait_close()?;
-    // println!("Initial PWD response: '{}'", raw_pwd.trim()); // Hiding debug output for cleaner UI
+/// Fills in the connection options of `profile` not given on the command line and
+/// returns its target
+fn apply_profile(profile: &config::HostProfile, ssh: &mut SshArgs) -> String {
+    println!("{} {}", "profile:".dimmed(), profile.target());
+    if ssh.identity.is_none() {
+        ssh.identity = profile.identity.clone();
+    }
+    if let Some(jump) = &profile.jump
+        && ssh.jump.is_empty()
+        && ssh.proxy_command.is_none()
+    {
+        ssh.jump = jump.split(',').map(|hop| hop.trim().to_string()).collect();
+    }
+    profile.target()
+}
 
-    let cwd = PathBuf::from(raw_pwd.trim());
+/// Sets up the session as `profile` asks: its environment for every command, its start
+/// directory and then its startup commands
+fn start_profile(
+    client: &mut ssh::SSHClient,
+    profile: &config::HostProfile,
+) -> Result<(), Box<dyn Error>> {
+    client.set_env(&profile.env)?;
+
+    if let Some(dir) = &profile.dir {
+        // Keep `~` unquoted so the remote shell expands it
+        let dir_arg = match dir.strip_prefix("~/") {
+            Some(rest) => format!("~/{}", shell::quote(rest)),
+            None if dir == "~" => dir.clone(),
+            None => shell::quote(dir),
+        };
+        let result = client.exec(&format!("cd {} && pwd -P", dir_arg))?;
+        if result.exit_status == 0 {
+            client.set_cwd(PathBuf::from(result.stdout.trim()));
+        } else {
+            eprint!("{} {}", "Could not change to".red(), result.stderr.red());
+        }
+    }
 
-    let client = SSHClient {
-        session,
-        host_name: hostname.to_string(),
-        user: user.to_string(),
-        port: port.to_string(),
-        current_directory: cwd,
-    };
+    for command in &profile.startup {
+        println!("{} {}", "startup:".dimmed(), command);
+        repl::run_remote_command(client, command)?;
+    }
+    Ok(())
+}
 
-    repl::repl(client)?;
+/// Splits `USER@HOSTNAME[:PORT]` into its parts
+fn parse_target(user_hostname: &str) -> Result<(&str, &str, Option<&str>), Box<dyn Error>> {
+    let user_hostname_vect: Vec<&str> = user_hostname.split("@").collect();
+    if user_hostname_vect.len() != 2 {
+        eprintln!("{}", "Misformatted USER@HOSTNAME[:PORT]!".red().bold());
+        return Err("Incorrect target address formatting!".into());
+    }
 
-    Ok(())
+    let user = user_hostname_vect[0];
+    let hostname_port: Vec<&str> = user_hostname_vect[1].split(":").collect();
+    match hostname_port.len() {
+        1 => Ok((user, hostname_port[0], None)),
+        2 => Ok((user, hostname_port[0], Some(hostname_port[1]))),
+        _ => {
+            eprintln!("{}", "Misformatted USER@HOSTNAME:PORT!".red().bold());
+            Err("Incorrect target address formatting!".into())
+        }
+    }
 }
@@ -1,4 +1,5 @@
-use crate::SSHClient;
+use crate::backend::{FileStat, RemoteBackend};
+use chrono::{Local, TimeZone};
 use colored::*;
 use rustyline::{DefaultEditor, error::ReadlineError};
 use std::{
@@ -6,20 +7,21 @@ use std::{
     env,
     error::Error,
     fs,
-    io::Write,
-    io::{self, Read},
-    path::PathBuf,
+    io::{self, Write},
+    path::{Path, PathBuf},
     process::Command,
     time::SystemTime,
 };
 
 type ReplResult = Result<(), Box<dyn Error>>;
-type CommandFunc = fn(&mut SSHClient, &mut DefaultEditor, &[&str]) -> ReplResult;
+type CommandFunc = fn(&mut dyn RemoteBackend, &mut DefaultEditor, &[&str]) -> ReplResult;
 
 struct ReplCommand {
     name: String,
     description: String,
     function: CommandFunc,
+    /// Whether the command only reads, so it can run again after a reconnect
+    retry: bool,
 }
 
 fn get_commands() -> HashMap<String, ReplCommand> {
@@ -31,13 +33,13 @@ fn get_commands() -> HashMap<String, ReplCommand> {
             name: "list".to_string(),
             description: "Aliases to remote 'ls'".to_string(),
             function: |client, _, args| {
-                let cmd = format!(
-                    "ls \"{}\" -lah {}",
-                    client.current_directory.display(),
-                    args.join(" ")
-                );
+                if !client.can_exec() {
+                    return print_listing(client, args);
+                }
+                let cmd = format!("ls \"{}\" -lah {}", client.cwd().display(), args.join(" "));
                 run_remote_command(client, &cmd)
             },
+            retry: true,
         },
     );
 
@@ -52,10 +54,16 @@ fn get_commands() -> HashMap<String, ReplCommand> {
                     return Ok(());
                 }
                 let target = args[0];
-                let path = client.current_directory.join(target);
+                let path = client.cwd().join(target);
+                if !client.can_exec() {
+                    let mut remote_file = client.open_read(&path)?;
+                    io::copy(&mut remote_file, &mut io::stdout())?;
+                    return Ok(());
+                }
                 let cmd = format!("cat \"{}\"", path.display());
                 run_remote_command(client, &cmd)
             },
+            retry: true,
         },
     );
 
@@ -65,9 +73,10 @@ fn get_commands() -> HashMap<String, ReplCommand> {
             name: "cwd".to_string(),
             description: "Prints effective working directory".to_string(),
             function: |client, _, _| {
-                println!("{}", client.current_directory.display().to_string().cyan());
+                println!("{}", client.cwd().display().to_string().cyan());
                 Ok(())
             },
+            retry: true,
         },
     );
 
@@ -82,69 +91,50 @@ fn get_commands() -> HashMap<String, ReplCommand> {
                 let new_path = if target.starts_with("/") || target.starts_with("~") {
                     PathBuf::from(target)
                 } else {
-                    client.current_directory.join(target)
+                    client.cwd().join(target)
                 };
 
-                let cmd = format!("cd \"{}\" && pwd", new_path.display());
-                let mut channel = client.session.channel_session()?;
-                channel.exec(&cmd)?;
-
-                let mut output = String::new();
-                let mut stderr = String::new();
-                channel.read_to_string(&mut output)?;
-                channel.stderr().read_to_string(&mut stderr)?;
-                channel.wait_close()?;
+                if !client.can_exec() {
+                    let resolved_path = client.realpath(&new_path)?;
+                    if client.stat(&resolved_path)?.is_dir {
+                        println!("{} {}.", "Changed dir to".green(), resolved_path.display());
+                        client.set_cwd(resolved_path);
+                    } else {
+                        eprintln!("{}", "Error: Not a directory!".red());
+                    }
+                    return Ok(());
+                }
 
-                let exit_status = channel.exit_status()?;
+                let cmd = format!("cd \"{}\" && pwd", new_path.display());
+                let result = client.exec(&cmd)?;
+                let output = result.stdout;
+                let stderr = result.stderr;
+                let exit_status = result.exit_status;
 
                 if exit_status == 0 && !output.trim().is_empty() {
                     let resolved_path = output.trim();
                     println!("{} {}.", "Changed dir to".green(), resolved_path);
-                    client.current_directory = PathBuf::from(resolved_path);
+                    client.set_cwd(PathBuf::from(resolved_path));
                     return Ok(());
                 }
 
                 // Fallback for servers without 'cd' binary
                 let combined_out = format!("{}{}", output, stderr);
-                if combined_out.contains("exec: \"cd\"")
-                    || combined_out.contains("cd: command not found")
+                if (combined_out.contains("exec: \"cd\"")
+                    || combined_out.contains("cd: command not found"))
+                    && let Ok(stat) = client.stat(&new_path)
                 {
-                    // Try SFTP first
-                    if let Ok(sftp) = client.session.sftp()
-                        && let Ok(stat) = sftp.stat(&new_path)
-                    {
-                        if stat.is_dir() {
-                            println!(
-                                "{} {}.",
-                                "(Local) Changed dir to".green(),
-                                new_path.display()
-                            );
-                            client.current_directory = new_path;
-                            return Ok(());
-                        } else {
-                            eprintln!("{}", "Error: Not a directory!".red());
-                            return Ok(());
-                        }
-                    }
-
-                    // Fallback: ls -d
-                    let ls_cmd = format!("ls -d \"{}\"", new_path.display());
-                    let mut ls_channel = client.session.channel_session()?;
-                    ls_channel.exec(&ls_cmd)?;
-
-                    let mut tmp = String::new();
-                    ls_channel.read_to_string(&mut tmp)?;
-
-                    ls_channel.wait_close()?;
-                    if ls_channel.exit_status()? == 0 {
+                    if stat.is_dir {
                         println!(
                             "{} {}.",
-                            "(Local-Force) Changed dir to".green(),
+                            "(Local) Changed dir to".green(),
                             new_path.display()
                         );
-                        client.current_directory = new_path;
-                        return Ok(());
+                        client.set_cwd(new_path);
+                    } else {
+                        eprintln!("{}", "Error: Not a directory!".red());
                     }
+                    return Ok(());
                 }
 
                 if !stderr.is_empty() {
@@ -162,6 +152,17 @@ fn get_commands() -> HashMap<String, ReplCommand> {
 
                 Ok(())
             },
+            retry: true,
+        },
+    );
+
+    commands.insert(
+        "upload".to_string(),
+        ReplCommand {
+            name: "upload".to_string(),
+            description: "Upload local file/folder to remote".to_string(),
+            function: cmd_upload,
+            retry: false,
         },
     );
 
@@ -171,6 +172,7 @@ fn get_commands() -> HashMap<String, ReplCommand> {
             name: "edit".to_string(),
             description: "Edit locally".to_string(),
             function: cmd_edit,
+            retry: false,
         },
     );
 
@@ -180,6 +182,7 @@ fn get_commands() -> HashMap<String, ReplCommand> {
             name: "copy".to_string(),
             description: "Copy file/folder to local filesystem".to_string(),
             function: cmd_copy,
+            retry: true,
         },
     );
 
@@ -187,32 +190,24 @@ fn get_commands() -> HashMap<String, ReplCommand> {
 }
 
 fn fetch_remote_resource(
-    client: &mut SSHClient,
-    remote_path: &PathBuf,
-    local_path: &PathBuf,
+    client: &mut dyn RemoteBackend,
+    remote_path: &Path,
+    local_path: &Path,
 ) -> Result<bool, Box<dyn Error>> {
     println!("{} {}.", "Fetching".cyan(), remote_path.display());
 
-    let is_dir = if let Ok(sftp) = client.session.sftp() {
-        match sftp.stat(remote_path) {
-            Ok(file_stat) => file_stat.is_dir(),
-            Err(_) => false,
-        }
-    } else {
-        let cmd = format!("ls -ld \"{}\"", remote_path.display());
-        let mut channel = client.session.channel_session()?;
-        channel.exec(&cmd)?;
-        let mut output = String::new();
-        channel.read_to_string(&mut output)?;
-        channel.wait_close()?;
-        output.trim().starts_with('d')
+    let is_dir = match client.stat(remote_path) {
+        Ok(file_stat) => file_stat.is_dir,
+        Err(_) => false,
     };
 
-    if is_dir {
+    if is_dir && !client.can_stream() {
+        fetch_dir_recursive(client, remote_path, local_path)?;
+    } else if is_dir {
         // Directory: Use remote tar -> local tar
         // Remote: tar -cf - -C <parent> <dirname>
-        let (parent, dirname) = if remote_path == &client.current_directory {
-            (remote_path.clone(), ".".to_string())
+        let (parent, dirname) = if remote_path == client.cwd() {
+            (remote_path.to_path_buf(), ".".to_string())
         } else {
             (
                 remote_path
@@ -230,10 +225,8 @@ fn fetch_remote_resource(
         fs::create_dir_all(local_path)?;
 
         let cmd_str = format!("tar -cf - -C {} {}", parent.display(), dirname);
-        let mut channel = client.session.channel_session()?;
-        channel.exec(&cmd_str)?;
 
-        // Spawn local tar to extract reading from channel stdout
+        // Spawn local tar to extract reading from remote stdout
         let mut child = Command::new("tar")
             .arg("-xf")
             .arg("-")
@@ -244,11 +237,19 @@ fn fetch_remote_resource(
             .spawn()?;
 
         let mut stdin = child.stdin.take().expect("Failed to open stdin");
-        io::copy(&mut channel, &mut stdin)?;
+        let result = client.exec_stream(&cmd_str, None, &mut stdin);
+        drop(stdin);
         child.wait()?;
-        channel.wait_close()?;
+
+        if !matches!(result, Ok((_, 0))) {
+            println!(
+                "{}",
+                "Remote tar unavailable, copying file by file.".yellow()
+            );
+            fetch_dir_recursive(client, remote_path, local_path)?;
+        }
     } else {
-        let (mut remote_file, _stat) = client.session.scp_recv(remote_path)?;
+        let mut remote_file = client.open_read(remote_path)?;
         let mut local_file = fs::File::create(local_path)?;
         io::copy(&mut remote_file, &mut local_file)?;
     }
@@ -256,7 +257,59 @@ fn fetch_remote_resource(
     Ok(is_dir)
 }
 
-fn cmd_copy(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> ReplResult {
+/// Per-file directory download for remotes that cannot run tar
+fn fetch_dir_recursive(
+    client: &mut dyn RemoteBackend,
+    remote_path: &Path,
+    local_path: &Path,
+) -> ReplResult {
+    fs::create_dir_all(local_path)?;
+    for entry in client.read_dir(remote_path)? {
+        let local_entry = local_path.join(entry.path.file_name().unwrap_or_default());
+        if entry.stat.is_dir {
+            fetch_dir_recursive(client, &entry.path, &local_entry)?;
+        } else {
+            let mut remote_file = client.open_read(&entry.path)?;
+            let mut local_file = fs::File::create(&local_entry)?;
+            io::copy(&mut remote_file, &mut local_file)?;
+        }
+    }
+    Ok(())
+}
+
+/// Uploads one file, only returning once the remote confirmed the write
+fn push_file(client: &mut dyn RemoteBackend, local_path: &Path, remote_path: &Path) -> ReplResult {
+    let mut local_file = fs::File::open(local_path)?;
+    let size = local_file.metadata()?.len();
+    let mut remote_file = client.open_write(remote_path, size)?;
+    io::copy(&mut local_file, &mut remote_file)?;
+    remote_file.finish()?;
+    Ok(())
+}
+
+/// Per-file directory upload for remotes that cannot run tar
+fn push_dir_recursive(
+    client: &mut dyn RemoteBackend,
+    local_path: &Path,
+    remote_path: &Path,
+) -> ReplResult {
+    if client.stat(remote_path).is_err() {
+        client.mkdir(remote_path)?;
+    }
+    for entry in fs::read_dir(local_path)? {
+        let entry = entry?;
+        let remote_entry = remote_path.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            push_dir_recursive(client, &entry.path(), &remote_entry)?;
+        } else {
+            push_file(client, &entry.path(), &remote_entry)?;
+        }
+    }
+    Ok(())
+}
+
+fn cmd_copy(client: &mut dyn RemoteBackend, rl: &mut DefaultEditor, args: &[&str]) -> ReplResult {
     let target = if let Some(arg) = args.first() {
         arg.to_string()
     } else {
@@ -285,7 +338,7 @@ fn cmd_copy(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
         }
     };
 
-    let remote_path = client.current_directory.join(&target);
+    let remote_path = client.cwd().join(&target);
     let local_path = PathBuf::from(&destination).join(remote_path.file_name().unwrap_or_default());
 
     fetch_remote_resource(client, &remote_path, &local_path)?;
@@ -294,7 +347,7 @@ fn cmd_copy(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
     Ok(())
 }
 
-fn cmd_edit(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> ReplResult {
+fn cmd_edit(client: &mut dyn RemoteBackend, rl: &mut DefaultEditor, args: &[&str]) -> ReplResult {
     let target = if let Some(arg) = args.first() {
         arg.to_string()
     } else {
@@ -306,7 +359,7 @@ fn cmd_edit(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
         }
     };
 
-    let remote_path = client.current_directory.join(&target);
+    let remote_path = client.cwd().join(&target);
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_nanos();
@@ -324,7 +377,13 @@ fn cmd_edit(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
     };
     let local_path = temp_base.join(&local_name);
 
-    let is_dir = fetch_remote_resource(client, &remote_path, &local_path)?;
+    let is_dir = match fetch_remote_resource(client, &remote_path, &local_path) {
+        Ok(is_dir) => is_dir,
+        Err(e) => {
+            fs::remove_dir_all(&temp_base).ok();
+            return Err(e);
+        }
+    };
 
     let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
     println!("{} {}.", "Opening in".cyan(), editor);
@@ -346,23 +405,58 @@ fn cmd_edit(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
 
     if response.trim().eq_ignore_ascii_case("n") {
         println!("{}", "Not syncing changes.".dimmed());
+        fs::remove_dir_all(&temp_base).ok();
         return Ok(());
     }
 
-    // Upload (Copy Back)
+    // Upload (Copy Back). Only this step is retried after a dropped connection, so the
+    // edited copy is never fetched again
     println!("{}", "Syncing back.".cyan());
-    if is_dir {
-        let (local_parent, local_dirname, remote_dest) = if target == "." {
+    let whole_dir = target == ".";
+    if let Err(e) = with_reconnect(client, true, |client| {
+        sync_back(client, &local_path, &remote_path, is_dir, whole_dir)
+    }) {
+        eprintln!(
+            "{} {}",
+            "Your edits are kept in".yellow(),
+            local_path.display()
+        );
+        return Err(e);
+    }
+
+    fs::remove_dir_all(&temp_base).ok();
+    println!("{}", "Done.".green());
+
+    Ok(())
+}
+
+/// Uploads an edited copy from `local_path` back over `remote_path`. `whole_dir` is set
+/// when the working directory itself was edited.
+fn sync_back(
+    client: &mut dyn RemoteBackend,
+    local_path: &Path,
+    remote_path: &Path,
+    is_dir: bool,
+    whole_dir: bool,
+) -> ReplResult {
+    if is_dir && !client.can_stream() {
+        push_dir_recursive(client, local_path, remote_path)?;
+    } else if is_dir {
+        let (local_parent, local_dirname, remote_dest) = if whole_dir {
             (
-                local_path.clone(),
+                local_path.to_path_buf(),
                 ".".to_string(),
-                client.current_directory.clone(),
+                client.cwd().to_path_buf(),
             )
         } else {
             (
-                temp_base.clone(),
-                local_name.clone(),
-                remote_path.parent().unwrap().to_path_buf(),
+                local_path.parent().unwrap_or(local_path).to_path_buf(),
+                local_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                remote_path.parent().unwrap_or(remote_path).to_path_buf(),
             )
         };
 
@@ -380,89 +474,246 @@ fn cmd_edit(client: &mut SSHClient, rl: &mut DefaultEditor, args: &[&str]) -> Re
 
         // Remote extract
         let remote_tar_cmd = format!("tar -xf - -C {} --overwrite", remote_dest.display());
-        let mut channel = client.session.channel_session()?;
-        channel.exec(&remote_tar_cmd)?;
+        let mut output = Vec::new();
+        let result = client.exec_stream(&remote_tar_cmd, Some(&mut tar_stdout), &mut output);
+        drop(tar_stdout);
+        tar_cmd.wait()?;
 
-        io::copy(&mut tar_stdout, &mut channel)?;
-        channel.send_eof()?;
+        if matches!(result, Ok((_, 0))) {
+            if !output.is_empty() {
+                eprintln!(
+                    "{} {}",
+                    "Remote tar output:".yellow(),
+                    String::from_utf8_lossy(&output)
+                );
+            }
+        } else {
+            println!(
+                "{}",
+                "Remote tar unavailable, copying file by file.".yellow()
+            );
+            push_dir_recursive(client, local_path, remote_path)?;
+        }
+    } else {
+        push_file(client, local_path, remote_path)?;
+    }
+    Ok(())
+}
 
-        let mut output = String::new();
-        channel.read_to_string(&mut output)?;
-        if !output.is_empty() {
-            eprintln!("{} {}", "Remote tar output:".yellow(), output);
+fn cmd_upload(client: &mut dyn RemoteBackend, rl: &mut DefaultEditor, args: &[&str]) -> ReplResult {
+    let source = if let Some(arg) = args.first() {
+        arg.to_string()
+    } else {
+        let input = rl.readline("Local file/Dir to upload: ")?;
+        if input.trim().is_empty() {
+            eprintln!("{}", "Usage: upload <local_path> [remote_dir].".red());
+            return Ok(());
         }
+        input.trim().to_string()
+    };
 
-        channel.wait_close()?;
-        tar_cmd.wait()?;
+    let local_path = PathBuf::from(&source);
+    let remote_dir = match args.get(1) {
+        Some(arg) => client.cwd().join(arg),
+        None => client.cwd().to_path_buf(),
+    };
+    let remote_path = remote_dir.join(local_path.file_name().unwrap_or_default());
+
+    println!("{} {}.", "Uploading".cyan(), local_path.display());
+    let metadata = fs::metadata(&local_path)?;
+    if metadata.is_dir() {
+        push_dir_recursive(client, &local_path, &remote_path)?;
     } else {
-        // File: scp
-        let mut local_file = fs::File::open(&local_path)?;
-        let metadata = fs::metadata(&local_path)?;
-        let mut remote_file = client
-            .session
-            .scp_send(&remote_path, 0o644, metadata.len(), None)?;
-        io::copy(&mut local_file, &mut remote_file)?;
+        push_file(client, &local_path, &remote_path)?;
     }
+    println!("{} {}.", "Uploaded to".green(), remote_path.display());
 
-    fs::remove_dir_all(&temp_base).ok();
-    println!("{}", "Done.".green());
+    Ok(())
+}
+
+/// `ls -lah` style listing built from `read_dir`, for backends without a shell
+fn print_listing(client: &mut dyn RemoteBackend, args: &[&str]) -> ReplResult {
+    let dir = match args.iter().find(|arg| !arg.starts_with('-')) {
+        Some(arg) => client.cwd().join(arg),
+        None => client.cwd().to_path_buf(),
+    };
+
+    let mut entries = client.read_dir(&dir)?;
+    entries.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name()));
+
+    for entry in entries {
+        let name = entry
+            .path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let stat = &entry.stat;
+        println!(
+            "{} {:>5} {:>5} {:>5} {} {}",
+            format_mode(stat),
+            stat.uid.map(|u| u.to_string()).unwrap_or_default(),
+            stat.gid.map(|g| g.to_string()).unwrap_or_default(),
+            stat.size.map(format_size).unwrap_or_default(),
+            stat.mtime.map(format_mtime).unwrap_or_default(),
+            if stat.is_dir {
+                name.blue().bold().to_string()
+            } else {
+                name
+            }
+        );
+    }
 
     Ok(())
 }
 
-fn run_remote_command(client: &mut SSHClient, cmd: &str) -> ReplResult {
-    let full_cmd = format!("cd \"{}\" && {}", client.current_directory.display(), cmd);
+fn format_mode(stat: &FileStat) -> String {
+    let Some(perm) = stat.perm else {
+        return if stat.is_dir {
+            "d?????????"
+        } else {
+            "-?????????"
+        }
+        .to_string();
+    };
+
+    let file_type = match perm & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ if stat.is_dir => 'd',
+        _ => '-',
+    };
+
+    let mut mode = String::from(file_type);
+    for shift in [6, 3, 0] {
+        let bits = (perm >> shift) & 0o7;
+        mode.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        mode.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    mode
+}
+
+/// Human readable size, rounded up like `ls -h`
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    value /= 1024.0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if value < 10.0 {
+        format!("{:.1}{}", (value * 10.0).ceil() / 10.0, UNITS[unit])
+    } else {
+        format!("{}{}", value.ceil(), UNITS[unit])
+    }
+}
+
+/// Modification time like `ls -l`: time of day for recent files, year otherwise
+fn format_mtime(mtime: u64) -> String {
+    let Some(time) = Local.timestamp_opt(mtime as i64, 0).single() else {
+        return String::new();
+    };
+
+    let six_months = chrono::Duration::days(182);
+    if (Local::now() - time).abs() < six_months {
+        time.format("%b %e %H:%M").to_string()
+    } else {
+        time.format("%b %e  %Y").to_string()
+    }
+}
+
+/// Runs `cmd` in the current directory, as `!` does, and prints its output
+pub fn run_remote_command(client: &mut dyn RemoteBackend, cmd: &str) -> ReplResult {
+    if client.passthrough() {
+        let result = client.exec(cmd.trim())?;
+        println!("{}", result.stdout);
+        return Ok(());
+    }
+
+    if !client.can_exec() {
+        eprintln!("{}", "This connection has no remote shell!".red());
+        return Ok(());
+    }
 
-    let mut channel = client.session.channel_session()?;
-    channel.exec(&full_cmd)?;
-    let mut output = String::new();
-    let mut stderr = String::new();
+    let full_cmd = format!("cd \"{}\" && {}", client.cwd().display(), cmd);
 
-    // Read both stdout and stderr
-    channel.read_to_string(&mut output)?;
-    channel.stderr().read_to_string(&mut stderr)?;
-    channel.wait_close()?;
+    let result = client.exec(&full_cmd)?;
 
     // Check for "cd" executable error
-    let combined_output = format!("{}{}", output, stderr);
-    if combined_output.contains("exec: \"cd\": executable file not found")
+    let combined_output = format!("{}{}", result.stdout, result.stderr);
+    let result = if combined_output.contains("exec: \"cd\": executable file not found")
         || combined_output.contains("cd: command not found")
     {
         // Fallback: run command directly without cd prefix
         // println!("(Server doesn't support 'cd', running raw command).");
-        let mut channel_retry = client.session.channel_session()?;
-        channel_retry.exec(cmd)?;
+        client.exec(cmd)?
+    } else {
+        result
+    };
 
-        let mut output_retry = String::new();
-        channel_retry.read_to_string(&mut output_retry)?;
-        let mut stderr_retry = String::new();
-        channel_retry.stderr().read_to_string(&mut stderr_retry)?;
+    println!("{}", result.stdout);
+    if !result.stderr.is_empty() {
+        eprint!("{}", result.stderr.red());
+    }
 
-        println!("{}", output_retry);
-        if !stderr_retry.is_empty() {
-            eprint!("{}", stderr_retry.red());
-        }
-        channel_retry.wait_close()?;
-    } else {
-        println!("{}", output);
-        if !stderr.is_empty() {
-            eprint!("{}", stderr.red());
+    Ok(())
+}
+
+/// Runs `action`, and when it fails because the connection dropped (while idle or
+/// underneath it), reconnects. Only a `retry` action, one that is safe to repeat, runs
+/// a second time, anything else is left for the user to check and run again.
+fn with_reconnect(
+    client: &mut dyn RemoteBackend,
+    retry: bool,
+    mut action: impl FnMut(&mut dyn RemoteBackend) -> ReplResult,
+) -> ReplResult {
+    match action(client) {
+        Err(e) if !client.is_alive() => {
+            eprintln!("{} {}", "Command Error:".red().bold(), e);
+            reconnect(client)?;
+            if !retry {
+                println!(
+                    "{}",
+                    "The command was not run again, check its effect before repeating it.".yellow()
+                );
+                return Ok(());
+            }
+            println!("{}", "Retrying the command.".yellow());
+            action(client)
         }
+        result => result,
     }
+}
 
+/// Reconnects `client` and brings it back to the working directory it had
+fn reconnect(client: &mut dyn RemoteBackend) -> ReplResult {
+    println!("{}", "Connection lost, reconnecting.".yellow().bold());
+    let cwd = client.cwd().to_path_buf();
+    client.reconnect()?;
+    client.set_cwd(cwd);
+    println!("{}", "✔ Reconnected.".green().bold());
     Ok(())
 }
 
-pub fn repl(mut shell_client: SSHClient) -> Result<(), Box<dyn Error>> {
+pub fn repl(shell_client: &mut dyn RemoteBackend) -> Result<(), Box<dyn Error>> {
     let mut rl = DefaultEditor::new()?;
     let commands = get_commands();
 
     loop {
         // Style the prompt: trump > user@host:port >
-        let prompt_str = format!(
-            "trump > {}@{}:{} > ",
-            shell_client.user, shell_client.host_name, shell_client.port
-        );
+        let prompt_str = format!("trump > {} > ", shell_client.label());
         // Rustyline doesn't easily support colored strings with ansi codes in the prompt width calc
         // without some extra work, but we can try passing the colored string directly.
         // It might mess up cursor positioning if the length isn't calculated sans-codes.
@@ -493,14 +744,21 @@ pub fn repl(mut shell_client: SSHClient) -> Result<(), Box<dyn Error>> {
                     }
                     _ => {
                         if let Some(command) = commands.get(cmd_name) {
-                            if let Err(e) = (command.function)(&mut shell_client, &mut rl, args) {
+                            let result = with_reconnect(shell_client, command.retry, |client| {
+                                (command.function)(client, &mut rl, args)
+                            });
+                            if let Err(e) = result {
                                 eprintln!("{} {}", "Command Error:".red().bold(), e);
                             }
                         } else if let Some(stripped_prefix) = cmd_name.strip_prefix("!") {
-                            run_remote_command(
-                                &mut shell_client,
-                                format!("{} {}", stripped_prefix, &args.join(" ")).as_str(),
-                            )?;
+                            let cmd = format!("{} {}", stripped_prefix, &args.join(" "));
+                            // Remote commands are never repeated, they need not be idempotent
+                            let result = with_reconnect(shell_client, false, |client| {
+                                run_remote_command(client, &cmd)
+                            });
+                            if let Err(e) = result {
+                                eprintln!("{} {}", "Command Error:".red().bold(), e);
+                            }
                         } else {
                             println!("{} {}", "Unknown command!".red(), "Try 'help'.".yellow());
                         }
@@ -516,3 +774,269 @@ pub fn repl(mut shell_client: SSHClient) -> Result<(), Box<dyn Error>> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{BackendResult, DirEntry, RemoteWrite};
+    use crate::local::{LocalClient, local_connect};
+
+    fn stat(perm: Option<u32>, is_dir: bool) -> FileStat {
+        FileStat {
+            is_dir,
+            perm,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn format_mode_renders_type_and_permissions() {
+        assert_eq!(format_mode(&stat(Some(0o100644), false)), "-rw-r--r--");
+        assert_eq!(format_mode(&stat(Some(0o040755), true)), "drwxr-xr-x");
+        assert_eq!(format_mode(&stat(Some(0o120777), false)), "lrwxrwxrwx");
+        assert_eq!(format_mode(&stat(Some(0o020620), false)), "crw--w----");
+    }
+
+    #[test]
+    fn format_mode_falls_back_without_type_bits() {
+        // FTP LIST and similar only report the permission bits
+        assert_eq!(format_mode(&stat(Some(0o750), true)), "drwxr-x---");
+        assert_eq!(format_mode(&stat(Some(0o600), false)), "-rw-------");
+        assert_eq!(format_mode(&stat(None, true)), "d?????????");
+        assert_eq!(format_mode(&stat(None, false)), "-?????????");
+    }
+
+    #[test]
+    fn format_size_rounds_up_like_ls() {
+        assert_eq!(format_size(0), "0");
+        assert_eq!(format_size(1023), "1023");
+        assert_eq!(format_size(1024), "1.0K");
+        assert_eq!(format_size(1025), "1.1K");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(10 * 1024), "10K");
+        assert_eq!(format_size(10 * 1024 + 1), "11K");
+        assert_eq!(format_size(1024 * 1024), "1.0M");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0G");
+    }
+
+    /// Backend whose connection drops once, counting the reconnects
+    struct Flaky {
+        cwd: PathBuf,
+        alive: bool,
+        reconnects: usize,
+    }
+
+    impl RemoteBackend for Flaky {
+        fn label(&self) -> String {
+            "flaky".to_string()
+        }
+        fn cwd(&self) -> &Path {
+            &self.cwd
+        }
+        fn set_cwd(&mut self, path: PathBuf) {
+            self.cwd = path;
+        }
+        fn is_alive(&mut self) -> bool {
+            self.alive
+        }
+        fn reconnect(&mut self) -> BackendResult<()> {
+            self.alive = true;
+            self.reconnects += 1;
+            self.cwd = PathBuf::from("/home");
+            Ok(())
+        }
+        fn exec_stream(
+            &mut self,
+            _: &str,
+            _: Option<&mut dyn io::Read>,
+            _: &mut dyn Write,
+        ) -> BackendResult<(String, i32)> {
+            unimplemented!()
+        }
+        fn stat(&mut self, _: &Path) -> BackendResult<FileStat> {
+            unimplemented!()
+        }
+        fn read_dir(&mut self, _: &Path) -> BackendResult<Vec<DirEntry>> {
+            unimplemented!()
+        }
+        fn open_read(&mut self, _: &Path) -> BackendResult<Box<dyn io::Read + '_>> {
+            unimplemented!()
+        }
+        fn open_write(&mut self, _: &Path, _: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+            unimplemented!()
+        }
+        fn rename(&mut self, _: &Path, _: &Path) -> BackendResult<()> {
+            unimplemented!()
+        }
+        fn remove(&mut self, _: &Path) -> BackendResult<()> {
+            unimplemented!()
+        }
+        fn mkdir(&mut self, _: &Path) -> BackendResult<()> {
+            unimplemented!()
+        }
+    }
+
+    /// Runs an action through `with_reconnect` that drops the connection on its first
+    /// call, returning the result and how often the action ran
+    fn drop_once(client: &mut Flaky, retry: bool) -> (ReplResult, usize) {
+        let mut runs = 0;
+        let result = with_reconnect(client, retry, |client| {
+            runs += 1;
+            if runs == 1 {
+                return Err("Connection lost".into());
+            }
+            assert_eq!(client.cwd(), Path::new("/srv/app"));
+            Ok(())
+        });
+        (result, runs)
+    }
+
+    fn flaky(alive: bool) -> Flaky {
+        Flaky {
+            cwd: PathBuf::from("/srv/app"),
+            alive,
+            reconnects: 0,
+        }
+    }
+
+    #[test]
+    fn with_reconnect_retries_reads_in_the_same_directory() {
+        let mut client = flaky(false);
+        let (result, runs) = drop_once(&mut client, true);
+        assert!(result.is_ok());
+        assert_eq!((runs, client.reconnects), (2, 1));
+        assert_eq!(client.cwd(), Path::new("/srv/app"));
+    }
+
+    #[test]
+    fn with_reconnect_never_repeats_other_commands() {
+        let mut client = flaky(false);
+        let (result, runs) = drop_once(&mut client, false);
+        assert!(result.is_ok());
+        assert_eq!((runs, client.reconnects), (1, 1));
+    }
+
+    #[test]
+    fn with_reconnect_passes_errors_of_live_connections() {
+        let mut client = flaky(true);
+        let (result, runs) = drop_once(&mut client, true);
+        assert!(result.is_err());
+        assert_eq!((runs, client.reconnects), (1, 0));
+    }
+
+    /// Scratch directory holding `notes.txt` and `sub/inner.txt`, opened as a local
+    /// backend, the reference for how the commands behave
+    fn local_tree() -> (tempfile::TempDir, LocalClient) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "remember the milk\n").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub").join("inner.txt"), "inside\n").unwrap();
+        let client = local_connect(Some(dir.path().to_path_buf())).unwrap();
+        (dir, client)
+    }
+
+    fn run(client: &mut LocalClient, name: &str, args: &[&str]) -> ReplResult {
+        let mut rl = DefaultEditor::new().unwrap();
+        (get_commands()[name].function)(client, &mut rl, args)
+    }
+
+    #[test]
+    fn reading_commands_succeed() {
+        let (_dir, mut client) = local_tree();
+        run(&mut client, "list", &[]).unwrap();
+        run(&mut client, "cat", &["notes.txt"]).unwrap();
+        run(&mut client, "cwd", &[]).unwrap();
+    }
+
+    #[test]
+    fn cd_follows_existing_directories_only() {
+        let (dir, mut client) = local_tree();
+        let root = dir.path().canonicalize().unwrap();
+
+        run(&mut client, "cd", &["sub"]).unwrap();
+        assert_eq!(client.cwd(), root.join("sub"));
+        run(&mut client, "cd", &[".."]).unwrap();
+        assert_eq!(client.cwd(), root);
+        run(&mut client, "cd", &["missing"]).unwrap();
+        assert_eq!(client.cwd(), root);
+        run(&mut client, "cd", &["notes.txt"]).unwrap();
+        assert_eq!(client.cwd(), root);
+    }
+
+    #[test]
+    fn copy_fetches_files_and_directories() {
+        let (_dir, mut client) = local_tree();
+        let dest = tempfile::tempdir().unwrap();
+        let dest_str = dest.path().to_str().unwrap();
+
+        run(&mut client, "copy", &["notes.txt", dest_str]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.path().join("notes.txt")).unwrap(),
+            "remember the milk\n"
+        );
+        run(&mut client, "copy", &["sub", dest_str]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.path().join("sub").join("inner.txt")).unwrap(),
+            "inside\n"
+        );
+    }
+
+    #[test]
+    fn upload_pushes_files_and_directories() {
+        let (dir, mut client) = local_tree();
+        let source = tempfile::tempdir().unwrap();
+        fs::write(source.path().join("report.csv"), "a,b\n").unwrap();
+        fs::create_dir_all(source.path().join("site").join("css")).unwrap();
+        fs::write(
+            source.path().join("site").join("css").join("main.css"),
+            "p{}",
+        )
+        .unwrap();
+
+        let file = source.path().join("report.csv");
+        run(&mut client, "upload", &[file.to_str().unwrap()]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("report.csv")).unwrap(),
+            "a,b\n"
+        );
+
+        let site = source.path().join("site");
+        run(&mut client, "upload", &[site.to_str().unwrap(), "sub"]).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sub/site/css/main.css")).unwrap(),
+            "p{}"
+        );
+    }
+
+    /// `edit` without the editor: fetch, change the local copy, sync it back
+    #[test]
+    fn edit_round_trips_files_and_directories() {
+        let (dir, mut client) = local_tree();
+        let scratch = tempfile::tempdir().unwrap();
+
+        let remote = client.cwd().join("notes.txt");
+        let local = scratch.path().join("notes.txt");
+        assert!(!fetch_remote_resource(&mut client, &remote, &local).unwrap());
+        fs::write(&local, "remember the eggs\n").unwrap();
+        sync_back(&mut client, &local, &remote, false, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt")).unwrap(),
+            "remember the eggs\n"
+        );
+
+        let remote = client.cwd().join("sub");
+        let local = scratch.path().join("sub");
+        assert!(fetch_remote_resource(&mut client, &remote, &local).unwrap());
+        fs::write(local.join("inner.txt"), "changed\n").unwrap();
+        fs::write(local.join("added.txt"), "new\n").unwrap();
+        sync_back(&mut client, &local, &remote, true, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sub/inner.txt")).unwrap(),
+            "changed\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("sub/added.txt")).unwrap(),
+            "new\n"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::terminal::TerminalClient;
+use colored::*;
+use serialport::SerialPort;
+use std::{error::Error, time::Duration};
+
+/// Opens a serial line (or any pty) and attaches to the console running on it
+pub fn serial_connect(
+    device: &str,
+    baud: u32,
+) -> Result<TerminalClient<Box<dyn SerialPort>>, Box<dyn Error>> {
+    println!("{} {} at {} baud.", "Opening".cyan(), device, baud);
+    let port = serialport::new(device, baud)
+        .timeout(Duration::from_millis(100))
+        .open()
+        .map_err(|e| format!("Could not open {}: {}", device, e))?;
+
+    TerminalClient::connect(port, format!("serial:{}", device), "\r", device)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::backend::RemoteBackend;
+    use std::{
+        ffi::CStr,
+        fs::File,
+        io::{self, Read, Write},
+        os::fd::{AsRawFd, FromRawFd, OwnedFd},
+        path::Path,
+        process::{Child, Command},
+        thread,
+    };
+
+    /// Master and slave end of a new pty, plus the path of the slave
+    fn openpty() -> (File, File, String) {
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: the name, termios and winsize arguments may be null
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        assert_eq!(result, 0, "openpty failed: {}", io::Error::last_os_error());
+        let mut name = [0 as libc::c_char; 128];
+        // SAFETY: `master` is an open pty master and `name` holds `name.len()` bytes
+        assert_eq!(
+            unsafe { libc::ptsname_r(master, name.as_mut_ptr(), name.len()) },
+            0
+        );
+        // SAFETY: openpty returned two fresh descriptors that nothing else owns, and
+        // ptsname_r wrote a NUL terminated path
+        unsafe {
+            (
+                File::from(OwnedFd::from_raw_fd(master)),
+                File::from(OwnedFd::from_raw_fd(slave)),
+                CStr::from_ptr(name.as_ptr()).to_string_lossy().to_string(),
+            )
+        }
+    }
+
+    fn relay(mut from: File, mut to: File) {
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = from.read(&mut buf) {
+                if n == 0 || to.write_all(&buf[..n]).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Console stand-in: a shell on one pty, relayed to a second pty whose slave end is
+    /// the "serial line", like `socat pty,raw EXEC:sh,pty` would set up
+    struct Console {
+        shell: Child,
+        line: String,
+        _line_slave: File,
+    }
+
+    impl Console {
+        fn start(dir: &Path) -> Console {
+            let (shell_master, shell_slave, _) = openpty();
+            let shell = Command::new("sh")
+                .arg("-i")
+                .current_dir(dir)
+                .env("PS1", "console$ ")
+                .env("ENV", "")
+                .stdin(shell_slave.try_clone().unwrap())
+                .stdout(shell_slave.try_clone().unwrap())
+                .stderr(shell_slave)
+                .spawn()
+                .unwrap();
+            let (line_master, line_slave, line) = openpty();
+            // Until trump opens the line, its echo would feed the shell's prompt back in
+            // SAFETY: `line_slave` is an open tty and `termios` is only used once filled
+            unsafe {
+                let mut termios = std::mem::zeroed();
+                assert_eq!(libc::tcgetattr(line_slave.as_raw_fd(), &mut termios), 0);
+                libc::cfmakeraw(&mut termios);
+                assert_eq!(
+                    libc::tcsetattr(line_slave.as_raw_fd(), libc::TCSANOW, &termios),
+                    0
+                );
+            }
+            relay(
+                shell_master.try_clone().unwrap(),
+                line_master.try_clone().unwrap(),
+            );
+            relay(line_master, shell_master);
+            Console {
+                shell,
+                line,
+                _line_slave: line_slave,
+            }
+        }
+    }
+
+    impl Drop for Console {
+        fn drop(&mut self) {
+            self.shell.kill().ok();
+            self.shell.wait().ok();
+        }
+    }
+
+    #[test]
+    fn serial_console_runs_file_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path().canonicalize().unwrap();
+        std::fs::write(dir_path.join("motd"), "hello from the console\n").unwrap();
+        let console = Console::start(&dir_path);
+
+        let mut client = serial_connect(&console.line, 115200).unwrap();
+        assert!(client.can_exec());
+        assert_eq!(client.cwd(), dir_path);
+
+        let output = client.exec("echo $((6*7))").unwrap();
+        assert_eq!(output.stdout.trim(), "42");
+        assert_eq!(output.exit_status, 0);
+
+        let mut motd = String::new();
+        client
+            .open_read(&dir_path.join("motd"))
+            .unwrap()
+            .read_to_string(&mut motd)
+            .unwrap();
+        assert_eq!(motd, "hello from the console\n");
+
+        let mut writer = client.open_write(&dir_path.join("notes"), 6).unwrap();
+        writer.write_all(b"saved\n").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir_path.join("notes")).unwrap(),
+            "saved\n"
+        );
+
+        let mut names: Vec<String> = client
+            .read_dir(&dir_path)
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, ["motd", "notes"]);
+    }
+}
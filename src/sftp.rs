@@ -0,0 +1,136 @@
+use crate::backend::{BackendResult, DirEntry, FileStat, RemoteBackend, RemoteWrite};
+use crate::cli::SshArgs;
+use crate::ssh::Connector;
+use crate::ssh_config::SshTarget;
+use ssh2::{Session, Sftp};
+use std::{
+    error::Error,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// SSH connection that only uses the sftp subsystem, for servers running
+/// `internal-sftp` with `ForceCommand` where every exec request fails.
+pub struct SftpClient {
+    pub session: Session,
+    pub sftp: Sftp,
+    pub connector: Connector,
+    pub host_name: String,
+    pub user: String,
+    pub port: String,
+    pub current_directory: PathBuf,
+    pub home_directory: PathBuf,
+}
+
+pub fn sftp_connect(target: &SshTarget, options: &SshArgs) -> Result<SftpClient, Box<dyn Error>> {
+    let (session, connector) = Connector::connect(target, options)?;
+    let sftp = session.sftp()?;
+    let home = sftp.realpath(Path::new("."))?;
+
+    Ok(SftpClient {
+        session,
+        sftp,
+        connector,
+        host_name: target.host_name.clone(),
+        user: target.user.clone(),
+        port: target.port.clone().unwrap_or("22".to_string()),
+        current_directory: home.clone(),
+        home_directory: home,
+    })
+}
+
+impl RemoteWrite for ssh2::File {
+    fn finish(mut self: Box<Self>) -> BackendResult<()> {
+        self.close()?;
+        Ok(())
+    }
+}
+
+impl RemoteBackend for SftpClient {
+    fn label(&self) -> String {
+        format!("sftp:{}@{}:{}", self.user, self.host_name, self.port)
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn can_exec(&self) -> bool {
+        false
+    }
+
+    fn is_alive(&mut self) -> bool {
+        self.connector.is_alive(&self.session)
+    }
+
+    fn reconnect(&mut self) -> BackendResult<()> {
+        let session = self.connector.reconnect()?;
+        self.sftp = session.sftp()?;
+        self.session = session;
+        Ok(())
+    }
+
+    fn realpath(&mut self, path: &Path) -> BackendResult<PathBuf> {
+        let expanded = match path.strip_prefix("~") {
+            Ok(rest) => self.home_directory.join(rest),
+            Err(_) => path.to_path_buf(),
+        };
+        Ok(self.sftp.realpath(&expanded)?)
+    }
+
+    fn exec_stream(
+        &mut self,
+        _cmd: &str,
+        _stdin: Option<&mut dyn Read>,
+        _stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        Err("Shell commands are not available over sftp!".into())
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        Ok(FileStat::from(&self.sftp.stat(path)?))
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        Ok(self
+            .sftp
+            .readdir(path)?
+            .into_iter()
+            .map(|(path, stat)| DirEntry {
+                stat: FileStat::from(&stat),
+                path,
+            })
+            .collect())
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        Ok(Box::new(self.sftp.open(path)?))
+    }
+
+    fn open_write(&mut self, path: &Path, _size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        Ok(Box::new(self.sftp.create(path)?))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        self.sftp.rename(from, to, None)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        if self.sftp.stat(path)?.is_dir() {
+            self.sftp.rmdir(path)?;
+        } else {
+            self.sftp.unlink(path)?;
+        }
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        self.sftp.mkdir(path, 0o755)?;
+        Ok(())
+    }
+}
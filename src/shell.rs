@@ -0,0 +1,97 @@
+//! POSIX shell snippets shared by backends that only reach the remote through `sh`.
+//! Scripts take their paths as positional parameters (`$1`, `$2`).
+
+use crate::backend::{DirEntry, FileStat};
+use std::path::PathBuf;
+
+pub const STAT_SCRIPT: &str = "stat -L -c '%f %s %Y %u %g' -- \"$1\"";
+pub const READ_DIR_SCRIPT: &str =
+    "find \"$1\" -mindepth 1 -maxdepth 1 -exec stat -L -c '%f %s %Y %u %g %n' {} +";
+pub const RENAME_SCRIPT: &str = "mv -- \"$1\" \"$2\"";
+pub const REMOVE_SCRIPT: &str = "if [ -d \"$1\" ]; then rmdir -- \"$1\"; else rm -- \"$1\"; fi";
+pub const MKDIR_SCRIPT: &str = "mkdir -- \"$1\"";
+
+/// Quotes `arg` for safe interpolation into a shell command line
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Parses the output of `STAT_SCRIPT`
+pub fn parse_stat(output: &str) -> Option<FileStat> {
+    let fields: Vec<&str> = output.split_whitespace().collect();
+    parse_stat_fields(&fields)
+}
+
+/// Parses the output of `READ_DIR_SCRIPT`
+pub fn parse_read_dir(output: &str) -> Vec<DirEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.splitn(6, ' ').collect();
+            let (name, stat_fields) = fields.split_last()?;
+            Some(DirEntry {
+                path: PathBuf::from(name),
+                stat: parse_stat_fields(stat_fields)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `stat -c '%f %s %Y %u %g'` fields
+fn parse_stat_fields(fields: &[&str]) -> Option<FileStat> {
+    let [mode, size, mtime, uid, gid] = fields else {
+        return None;
+    };
+    let perm = u32::from_str_radix(mode, 16).ok()?;
+    Some(FileStat {
+        is_dir: perm & 0o170000 == 0o040000,
+        size: size.parse().ok(),
+        perm: Some(perm),
+        mtime: mtime.parse().ok(),
+        uid: uid.parse().ok(),
+        gid: gid.parse().ok(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn quote_escapes_single_quotes() {
+        assert_eq!(quote("plain"), "'plain'");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn parse_stat_reads_hex_mode() {
+        let stat = parse_stat("41ed 4096 1700000000 0 0\n").unwrap();
+        assert!(stat.is_dir);
+        assert_eq!(stat.perm, Some(0o040755));
+        assert_eq!(stat.size, Some(4096));
+        assert_eq!(stat.mtime, Some(1700000000));
+        assert_eq!(stat.uid, Some(0));
+
+        let stat = parse_stat("81a4 12 1700000000 1000 100").unwrap();
+        assert!(!stat.is_dir);
+        assert_eq!(stat.perm, Some(0o100644));
+        assert_eq!(stat.gid, Some(100));
+
+        assert!(parse_stat("stat: cannot statx 'x': No such file or directory").is_none());
+        assert!(parse_stat("").is_none());
+    }
+
+    #[test]
+    fn parse_read_dir_keeps_spaces_in_names() {
+        let entries = parse_read_dir(
+            "81a4 5 1700000000 1000 1000 /home/me/a file.txt\n\
+             41ed 4096 1700000000 1000 1000 /home/me/sub\n\
+             garbage line\n",
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, Path::new("/home/me/a file.txt"));
+        assert_eq!(entries[0].stat.size, Some(5));
+        assert!(entries[1].stat.is_dir);
+    }
+}
@@ -0,0 +1,452 @@
+//! Host aliases from `~/.ssh/config`, resolved the way OpenSSH does: for every option
+//! the first value from a matching `Host` block wins, `IdentityFile` and `CertificateFile`
+//! accumulate.
+
+use crate::cli::SshArgs;
+use colored::*;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// `Include` nesting limit, guards against include loops
+const MAX_INCLUDE_DEPTH: usize = 16;
+/// Jump host nesting limit, guards against `ProxyJump` loops
+const MAX_JUMP_DEPTH: usize = 8;
+
+/// Options collected for one host alias
+#[derive(Debug, Default)]
+struct HostConfig {
+    host_name: Option<String>,
+    user: Option<String>,
+    port: Option<String>,
+    identity_files: Vec<String>,
+    certificate_files: Vec<String>,
+    proxy_jump: Option<String>,
+    proxy_command: Option<String>,
+}
+
+/// Connection parameters for a `[USER@]HOST[:PORT]` target after applying `~/.ssh/config`
+#[derive(Clone)]
+pub struct SshTarget {
+    pub user: String,
+    pub host_name: String,
+    pub port: Option<String>,
+    pub identity: Option<PathBuf>,
+    /// Certificate presented with the identity, when not found next to it
+    pub certificate: Option<PathBuf>,
+    /// Hosts to tunnel through, in order, the last one opening the connection to this host
+    pub jump_hosts: Vec<SshTarget>,
+    /// Local command to run the connection over instead of a TCP socket, tokens expanded
+    pub proxy_command: Option<String>,
+}
+
+/// Splits `target` and fills in whatever it leaves out from the matching `Host` blocks.
+/// Values given on the command line (identity, jump hosts, proxy command) always win over
+/// the config file. Jump hosts are resolved the same way.
+pub fn resolve_target(target: &str, args: &SshArgs) -> Result<SshTarget, Box<dyn Error>> {
+    resolve(
+        target,
+        args.identity.clone(),
+        args.certificate.clone(),
+        &args.jump,
+        args.proxy_command.as_deref(),
+        0,
+    )
+}
+
+fn resolve(
+    target: &str,
+    identity: Option<PathBuf>,
+    certificate: Option<PathBuf>,
+    jump: &[String],
+    proxy_command: Option<&str>,
+    depth: usize,
+) -> Result<SshTarget, Box<dyn Error>> {
+    if depth > MAX_JUMP_DEPTH {
+        return Err(format!("Too many nested jump hosts at {}!", target).into());
+    }
+
+    let (user, host_port) = match target.split_once('@') {
+        Some((user, host_port)) => (Some(user), host_port),
+        None => (None, target),
+    };
+    let (alias, port) = match host_port.split_once(':') {
+        Some((alias, port)) => (alias, Some(port)),
+        None => (host_port, None),
+    };
+    if alias.is_empty() || user.is_some_and(str::is_empty) || port.is_some_and(str::is_empty) {
+        eprintln!("{}", "Misformatted [USER@]HOSTNAME[:PORT]!".red().bold());
+        return Err("Incorrect target address formatting!".into());
+    }
+
+    let mut config = HostConfig::default();
+    if let Some(ssh_dir) = ssh_dir() {
+        let path = ssh_dir.join("config");
+        if path.exists() {
+            read_config(&path, &alias.to_ascii_lowercase(), &mut config, 0)?;
+        }
+    }
+
+    let port = port.map(str::to_string).or(config.port.clone());
+    let port_or_default = port.as_deref().unwrap_or("22");
+    let host_name = match &config.host_name {
+        Some(host_name) => expand_tokens(host_name, alias, port_or_default, ""),
+        None => alias.to_string(),
+    };
+    let user = match user.map(str::to_string).or(config.user.clone()) {
+        Some(user) => user,
+        None => local_user().ok_or_else(|| format!("No user given for {}!", alias))?,
+    };
+
+    // Like ssh, only identity files that actually exist are offered
+    let identity = identity.or_else(|| {
+        config
+            .identity_files
+            .iter()
+            .map(|file| PathBuf::from(expand_tokens(file, &host_name, port_or_default, &user)))
+            .find(|path| path.exists())
+    });
+    let certificate = certificate.or_else(|| {
+        config
+            .certificate_files
+            .iter()
+            .map(|file| PathBuf::from(expand_tokens(file, &host_name, port_or_default, &user)))
+            .find(|path| path.exists())
+    });
+
+    if host_name != alias || config.user.is_some() || config.port.is_some() {
+        println!(
+            "{} {} -> {}@{}:{}",
+            "ssh_config:".dimmed(),
+            alias,
+            user,
+            host_name,
+            port_or_default
+        );
+    }
+
+    // Command line options replace both config settings, and ProxyJump wins over
+    // ProxyCommand when a host has both
+    let (jump, proxy_command): (Vec<String>, Option<String>) =
+        if !jump.is_empty() || proxy_command.is_some() {
+            (jump.to_vec(), proxy_command.map(str::to_string))
+        } else {
+            match (
+                config.proxy_jump.as_deref(),
+                config.proxy_command.as_deref(),
+            ) {
+                (Some(proxy_jump), _) if proxy_jump != "none" => {
+                    (proxy_jump.split(',').map(str::to_string).collect(), None)
+                }
+                (_, Some(proxy_command)) if proxy_command != "none" => {
+                    (Vec::new(), Some(proxy_command.to_string()))
+                }
+                _ => (Vec::new(), None),
+            }
+        };
+    let jump_hosts = jump
+        .iter()
+        .map(|hop| resolve(hop.trim(), None, None, &[], None, depth + 1))
+        .collect::<Result<_, _>>()?;
+    let proxy_command =
+        proxy_command.map(|command| expand_tokens(&command, &host_name, port_or_default, &user));
+
+    Ok(SshTarget {
+        user,
+        host_name,
+        port,
+        identity,
+        certificate,
+        jump_hosts,
+        proxy_command,
+    })
+}
+
+fn ssh_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".ssh"))
+}
+
+fn local_user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+}
+
+/// Applies the blocks of `path` that match `alias` to `config`, following `Include`
+fn read_config(
+    path: &Path,
+    alias: &str,
+    config: &mut HostConfig,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!("Too many nested Includes in {}!", path.display()).into());
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    // Options before the first `Host` line apply to every host
+    let mut active = true;
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, value) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+            Some(split) => (
+                &line[..split],
+                line[split..].trim_start_matches(|c: char| c.is_whitespace() || c == '='),
+            ),
+            None => (line, ""),
+        };
+        let args = split_args(value);
+
+        match keyword.to_ascii_lowercase().as_str() {
+            "host" => active = host_matches(&args, alias),
+            // Match criteria are not evaluated, so their blocks never apply
+            "match" => active = false,
+            "include" if active => {
+                for pattern in &args {
+                    for included in expand_include(pattern) {
+                        read_config(&included, alias, config, depth + 1)?;
+                    }
+                }
+            }
+            "hostname" if active => set_once(&mut config.host_name, &args),
+            "user" if active => set_once(&mut config.user, &args),
+            "port" if active => set_once(&mut config.port, &args),
+            "proxyjump" if active => set_once(&mut config.proxy_jump, &args),
+            // The command is handed to `sh -c` as a whole, so keep its quoting intact
+            "proxycommand" if active && config.proxy_command.is_none() => {
+                config.proxy_command = Some(value.to_string())
+            }
+            "identityfile" if active => config.identity_files.extend(args.into_iter().take(1)),
+            "certificatefile" if active => {
+                config.certificate_files.extend(args.into_iter().take(1))
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn set_once(option: &mut Option<String>, args: &[String]) {
+    if option.is_none() {
+        *option = args.first().cloned();
+    }
+}
+
+/// Splits an option value on whitespace, honouring double quotes
+fn split_args(value: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+    for c in value.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    args.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        args.push(current);
+    }
+    args
+}
+
+/// A `Host` line matches if any pattern does and no negated (`!`) pattern does
+fn host_matches(patterns: &[String], alias: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(&pattern, alias),
+        }
+    }
+    matched
+}
+
+/// Glob matching with `*` (any run) and `?` (any single character)
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Resolves an `Include` argument: relative to `~/.ssh`, wildcards in the file name
+fn expand_include(pattern: &str) -> Vec<PathBuf> {
+    let path = match (pattern.strip_prefix("~/"), dirs::home_dir(), ssh_dir()) {
+        (Some(rest), Some(home), _) => home.join(rest),
+        (None, _, Some(ssh_dir)) => ssh_dir.join(pattern),
+        _ => PathBuf::from(pattern),
+    };
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if !name.contains(['*', '?']) {
+        return if path.is_file() {
+            vec![path]
+        } else {
+            Vec::new()
+        };
+    }
+
+    let Some(parent) = path.parent() else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = fs::read_dir(parent)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| wildcard_match(&name, &entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    matches.sort();
+    matches
+}
+
+/// Expands `~` and the `%h`, `%p`, `%r`, `%u`, `%d` and `%%` tokens
+fn expand_tokens(value: &str, host: &str, port: &str, user: &str) -> String {
+    let home = dirs::home_dir()
+        .map(|home| home.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let value = match value.strip_prefix('~') {
+        Some(rest) => format!("{}{}", home, rest),
+        None => value.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('h') => expanded.push_str(host),
+            Some('p') => expanded.push_str(port),
+            Some('r') => expanded.push_str(user),
+            Some('u') => expanded.push_str(&local_user().unwrap_or_default()),
+            Some('d') => expanded.push_str(&home),
+            Some('%') => expanded.push('%'),
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_args_honours_quotes() {
+        assert_eq!(split_args("a  b\tc"), ["a", "b", "c"]);
+        assert_eq!(
+            split_args(r#""~/My Keys/id_ed25519" other"#),
+            ["~/My Keys/id_ed25519", "other"]
+        );
+        assert_eq!(split_args(r#""""#), [""]);
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn wildcard_match_globs() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("web-?", "web-1"));
+        assert!(!wildcard_match("web-?", "web-10"));
+        assert!(wildcard_match("*.example.com", "db.example.com"));
+        assert!(!wildcard_match("*.example.com", "example.com"));
+        assert!(wildcard_match("a*b*c", "aXXbYYbc"));
+        assert!(wildcard_match("exact", "exact"));
+        assert!(!wildcard_match("exact", "exactly"));
+    }
+
+    #[test]
+    fn host_matches_respects_negation() {
+        assert!(host_matches(&split_args("*.prod !bastion.prod"), "db.prod"));
+        assert!(!host_matches(
+            &split_args("*.prod !bastion.prod"),
+            "bastion.prod"
+        ));
+        assert!(!host_matches(&split_args("!bastion.prod"), "db.prod"));
+        assert!(host_matches(&split_args("Web"), "web"));
+    }
+
+    #[test]
+    fn expand_tokens_substitutes() {
+        assert_eq!(
+            expand_tokens("nc %h %p -u %r %% %x", "db.internal", "2222", "deploy"),
+            "nc db.internal 2222 -u deploy % %x"
+        );
+        let home = dirs::home_dir().unwrap().to_string_lossy().to_string();
+        assert_eq!(
+            expand_tokens("~/.ssh/id_%h", "web", "22", "me"),
+            format!("{}/.ssh/id_web", home)
+        );
+    }
+
+    #[test]
+    fn read_config_keeps_first_value() {
+        let path = std::env::temp_dir().join(format!("trump-ssh-config-{}", std::process::id()));
+        fs::write(
+            &path,
+            "User global\n\
+             Host web\n  HostName web.internal\n  Port=2200\n  IdentityFile ~/.ssh/web\n\
+             Host *\n  HostName fallback\n  Port 22\n  IdentityFile ~/.ssh/default\n",
+        )
+        .unwrap();
+
+        let mut config = HostConfig::default();
+        read_config(&path, "web", &mut config, 0).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(config.user.as_deref(), Some("global"));
+        assert_eq!(config.host_name.as_deref(), Some("web.internal"));
+        assert_eq!(config.port.as_deref(), Some("2200"));
+        assert_eq!(config.identity_files, ["~/.ssh/web", "~/.ssh/default"]);
+    }
+}
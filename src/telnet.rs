@@ -0,0 +1,145 @@
+use crate::terminal::TerminalClient;
+use colored::*;
+use std::{
+    collections::HashSet,
+    error::Error,
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const OPT_ECHO: u8 = 1;
+const OPT_SGA: u8 = 3;
+
+enum State {
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+/// TCP stream that strips telnet commands from the data and answers option
+/// negotiation: the server may echo and suppress go-ahead, everything else is refused.
+pub struct TelnetStream {
+    tcp: TcpStream,
+    state: State,
+    answered: HashSet<(u8, u8)>,
+}
+
+impl TelnetStream {
+    fn negotiate(&mut self, verb: u8, option: u8) -> io::Result<()> {
+        let reply = match verb {
+            WILL if option == OPT_ECHO || option == OPT_SGA => DO,
+            WILL => DONT,
+            DO if option == OPT_SGA => WILL,
+            DO => WONT,
+            // DONT/WONT need no answer when the option is already off
+            _ => return Ok(()),
+        };
+        // Only answer once per request to avoid negotiation loops
+        if self.answered.insert((verb, option)) {
+            self.tcp.write_all(&[IAC, reply, option])?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for TelnetStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = vec![0u8; buf.len()];
+        loop {
+            let n = self.tcp.read(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+
+            let mut len = 0;
+            for &byte in &raw[..n] {
+                self.state = match (&self.state, byte) {
+                    (State::Data, IAC) => State::Iac,
+                    (State::Data, 0) => State::Data,
+                    (State::Data, _) => {
+                        buf[len] = byte;
+                        len += 1;
+                        State::Data
+                    }
+                    (State::Iac, IAC) => {
+                        buf[len] = IAC;
+                        len += 1;
+                        State::Data
+                    }
+                    (State::Iac, WILL | WONT | DO | DONT) => State::Negotiate(byte),
+                    (State::Iac, SB) => State::Sub,
+                    (State::Iac, _) => State::Data,
+                    (State::Negotiate(verb), _) => {
+                        let verb = *verb;
+                        self.negotiate(verb, byte)?;
+                        State::Data
+                    }
+                    (State::Sub, IAC) => State::SubIac,
+                    (State::Sub, _) => State::Sub,
+                    (State::SubIac, SE) => State::Data,
+                    (State::SubIac, _) => State::Sub,
+                };
+            }
+
+            // A chunk of pure negotiation is not end of stream, keep reading
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+impl Write for TelnetStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // IAC bytes in the data must be doubled
+        let mut escaped = Vec::with_capacity(buf.len());
+        for &byte in buf {
+            escaped.push(byte);
+            if byte == IAC {
+                escaped.push(IAC);
+            }
+        }
+        self.tcp.write_all(&escaped)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tcp.flush()
+    }
+}
+
+pub fn telnet_connect(
+    hostname: &str,
+    port: Option<&str>,
+) -> Result<TerminalClient<TelnetStream>, Box<dyn Error>> {
+    let port = port.unwrap_or("23");
+
+    println!("{} {}:{}.", "Connecting to".cyan(), hostname, port);
+    let tcp = TcpStream::connect(format!("{hostname}:{port}"))?;
+    tcp.set_nodelay(true)?;
+    tcp.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    let stream = TelnetStream {
+        tcp,
+        state: State::Data,
+        answered: HashSet::new(),
+    };
+
+    TerminalClient::connect(
+        stream,
+        format!("telnet:{}:{}", hostname, port),
+        "\r\n",
+        &format!("{}:{}", hostname, port),
+    )
+}
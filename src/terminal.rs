@@ -0,0 +1,499 @@
+use crate::backend::{BackendResult, DirEntry, ExecOutput, FileStat, RemoteBackend, RemoteWrite};
+use crate::{config, credential_store, shell};
+use colored::*;
+use std::{
+    error::Error,
+    io::{self, Cursor, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Prompt installed on POSIX shells so command output can be framed reliably
+const PS1: &str = "__TRUMP_PS1__";
+/// Printed after every command on POSIX shells, followed by the command's sequence
+/// number and its exit status
+const RC_MARKER: &str = "__TRUMP_RC_";
+/// How long the remote has to stay silent before a prompt is assumed
+const QUIET: Duration = Duration::from_millis(1500);
+/// Raw CLIs give no completion marker, so fall back to silence after this long
+const RAW_QUIET: Duration = Duration::from_secs(3);
+/// How long shell setup and file commands may stay silent before they are interrupted
+const MARKER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Bytes per `printf` line when writing files through the shell
+const WRITE_CHUNK: usize = 256;
+
+const NEEDS_POSIX: &str = "File commands need a POSIX shell on the device!";
+
+/// What a remote that stops sending output means to `read_until`
+enum Silence {
+    /// The output is complete once the remote stays quiet this long
+    Ends(Duration),
+    /// The remote should have finished, so staying quiet this long is an error. The
+    /// running command is interrupted before the error is returned.
+    Fails(Duration),
+    /// The remote may take as long as it needs, e.g. for commands typed by the user
+    Waits,
+}
+
+/// Interactive shell reached over a byte stream (telnet, serial line).
+///
+/// Output is framed by prompts: on POSIX shells a known `PS1` plus an exit status
+/// marker is installed, other CLIs (switches, bootloaders) only get `!` passthrough
+/// framed by their own prompt. The stream's reads must time out rather than block.
+pub struct TerminalClient<S: Read + Write> {
+    stream: S,
+    name: String,
+    newline: &'static str,
+    prompt: String,
+    posix: bool,
+    /// Numbers the exit status markers, so output of an interrupted command is never
+    /// mistaken for that of a later one
+    sequence: u64,
+    pub current_directory: PathBuf,
+}
+
+impl<S: Read + Write> TerminalClient<S> {
+    /// Logs in if the remote asks for credentials and prepares the shell for framing.
+    /// Saved passwords are looked up as `user@<config_host>`.
+    pub fn connect(
+        stream: S,
+        name: String,
+        newline: &'static str,
+        config_host: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut client = TerminalClient {
+            stream,
+            name,
+            newline,
+            prompt: String::new(),
+            posix: false,
+            sequence: 0,
+            current_directory: PathBuf::new(),
+        };
+
+        client.login(config_host)?;
+        client.detect_shell()?;
+
+        if client.posix {
+            let output = client.run_posix("pwd", Silence::Fails(MARKER_TIMEOUT))?;
+            client.current_directory = PathBuf::from(output.stdout.trim());
+            println!("{}", "✔ POSIX shell detected.".green().bold());
+        } else {
+            println!(
+                "{}",
+                "No POSIX shell detected, only '!' commands are available.".yellow()
+            );
+        }
+
+        Ok(client)
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.stream.write_all(line.as_bytes())?;
+        self.stream.write_all(self.newline.as_bytes())?;
+        self.stream.flush()
+    }
+
+    /// Reads until `done` accepts the tail of the output, or the remote stays quiet
+    /// for as long as `silence` allows
+    fn read_until(
+        &mut self,
+        done: impl Fn(&str) -> bool,
+        silence: Silence,
+    ) -> BackendResult<String> {
+        let mut text = String::new();
+        let mut buf = [0u8; 4096];
+        let mut last_data = Instant::now();
+
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(format!("{} closed the connection!", self.name).into()),
+                Ok(n) => {
+                    text.push_str(&String::from_utf8_lossy(&buf[..n]).replace('\r', ""));
+                    last_data = Instant::now();
+                    if done(tail(&text)) {
+                        return Ok(text);
+                    }
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                    ) =>
+                {
+                    match silence {
+                        Silence::Ends(quiet) if last_data.elapsed() >= quiet => return Ok(text),
+                        Silence::Fails(timeout) if last_data.elapsed() >= timeout => {
+                            self.interrupt();
+                            return Err(format!(
+                                "{} sent nothing for {}s, interrupted the command!",
+                                self.name,
+                                timeout.as_secs()
+                            )
+                            .into());
+                        }
+                        _ => {}
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Sends Ctrl-C and drains the output up to the next prompt, so nothing of the
+    /// interrupted command is left for the next one to read
+    fn interrupt(&mut self) {
+        if self
+            .stream
+            .write_all(b"\x03")
+            .and_then(|_| self.stream.flush())
+            .is_ok()
+        {
+            self.read_until(|tail| tail.trim_end().ends_with(PS1), Silence::Ends(QUIET))
+                .ok();
+        }
+    }
+
+    fn login(&mut self, config_host: &str) -> BackendResult<()> {
+        let config = config::load_config().unwrap_or_else(|e| {
+            eprintln!("{} {}.", "Warning: Could not load config:".yellow(), e);
+            config::Config::default()
+        });
+
+        // Serial consoles sit silently at a prompt, so nudge them if nothing shows up
+        let mut output = self.read_until(ends_with_prompt, Silence::Ends(QUIET))?;
+        if !ends_with_prompt(tail(&output)) {
+            self.send_line("")?;
+            output = self.read_until(ends_with_prompt, Silence::Ends(QUIET))?;
+        }
+
+        let mut user = String::new();
+        let mut prompted = false;
+        let mut saved_tried = false;
+        let mut typed: Option<(String, String)> = None;
+
+        for _ in 0..6 {
+            let line = last_line(&output).to_string();
+            if is_login_prompt(&line) {
+                print!("{} ", line.trim().dimmed());
+                io::stdout().flush()?;
+                user.clear();
+                io::stdin().read_line(&mut user)?;
+                user = user.trim().to_string();
+                self.send_line(&user)?;
+            } else if is_password_prompt(&line) {
+                let config_key = format!("{}@{}", user, config_host);
+                let saved = config
+                    .targets
+                    .get(&config_key)
+                    .filter(|_| !saved_tried)
+                    .filter(|auth| matches!(auth.auth_type, config::AuthType::Password))
+                    .and_then(|auth| credential_store::load_secret(&auth.secret).ok());
+                if let Some(password) = saved {
+                    println!("{}", "Found saved password. Attempting auto-login.".cyan());
+                    saved_tried = true;
+                    self.send_line(&password)?;
+                } else {
+                    let password = rpassword::prompt_password("Password: ")?;
+                    self.send_line(&password)?;
+                    typed = Some((config_key, password));
+                }
+            } else {
+                break;
+            }
+            prompted = true;
+            output = self.read_until(ends_with_prompt, Silence::Ends(QUIET))?;
+        }
+
+        let line = last_line(&output).trim_end().to_string();
+        if line.is_empty() || is_login_prompt(&line) || is_password_prompt(&line) {
+            return Err(format!(
+                "{}",
+                "Authentication failed. Please check your credentials!"
+                    .red()
+                    .bold()
+            )
+            .into());
+        }
+        if prompted {
+            println!("{}", "✔ Logged in.".green().bold());
+        }
+        self.prompt = line;
+
+        if let Some((config_key, password)) = typed
+            && !config.targets.contains_key(&config_key)
+        {
+            config::offer_to_save_password(config, config_key, &password)?;
+        }
+
+        Ok(())
+    }
+
+    /// Probes for a POSIX shell and, if found, installs the framing prompt
+    fn detect_shell(&mut self) -> BackendResult<()> {
+        let probe = self.run_raw("echo __TRUMP_$((6*7))__")?;
+        self.posix = probe.contains("__TRUMP_42__");
+        if !self.posix {
+            return Ok(());
+        }
+
+        self.send_line(&format!(
+            "stty -echo 2>/dev/null; PS1='{}'' '; PS2=''; unset PROMPT_COMMAND",
+            unechoable(PS1)
+        ))?;
+        self.read_until(
+            |tail| tail.trim_end().ends_with(PS1),
+            Silence::Fails(MARKER_TIMEOUT),
+        )?;
+        Ok(())
+    }
+
+    /// Sends `cmd` to a CLI without framing support, returning output up to its prompt
+    fn run_raw(&mut self, cmd: &str) -> BackendResult<String> {
+        self.send_line(cmd)?;
+        let stem = prompt_stem(&self.prompt).to_string();
+        let output = self.read_until(
+            |tail| {
+                let line = last_line(tail).trim_end();
+                line.starts_with(&stem) && (line.ends_with(['#', '>', '$', '%']) || line == PS1)
+            },
+            Silence::Ends(RAW_QUIET),
+        )?;
+
+        // Drop the echoed command line and the trailing prompt
+        let mut lines: Vec<&str> = output.lines().collect();
+        if lines
+            .first()
+            .is_some_and(|l| l.trim_end().ends_with(cmd.trim()))
+        {
+            lines.remove(0);
+        }
+        if lines.last().is_some_and(|l| l.starts_with(&stem)) {
+            lines.pop();
+        }
+        Ok(lines.join("\n"))
+    }
+
+    fn run_posix(&mut self, cmd: &str, silence: Silence) -> BackendResult<ExecOutput> {
+        self.sequence += 1;
+        let rc_marker = format!("{}{}_", RC_MARKER, self.sequence);
+        self.send_line(&format!("{}; echo {}$?__", cmd, unechoable(&rc_marker)))?;
+        let output = self.read_until(
+            |tail| tail.contains(&rc_marker) && tail.trim_end().ends_with(PS1),
+            silence,
+        )?;
+
+        let marker = output.rfind(&rc_marker).unwrap_or(output.len());
+        let exit_status = output[marker..]
+            .trim_start_matches(&rc_marker)
+            .split("__")
+            .next()
+            .and_then(|rc| rc.parse().ok())
+            .unwrap_or(-1);
+
+        Ok(ExecOutput {
+            stdout: output[..marker].to_string(),
+            stderr: String::new(),
+            exit_status,
+        })
+    }
+
+    /// Runs a `shell` script with `args` as positional parameters, failing on non-zero exit
+    fn run_script(&mut self, script: &str, args: &[&Path]) -> BackendResult<String> {
+        if !self.posix {
+            return Err(NEEDS_POSIX.into());
+        }
+        let quoted: Vec<String> = args
+            .iter()
+            .map(|a| shell::quote(&a.to_string_lossy()))
+            .collect();
+        let output = self.run_posix(
+            &format!("set -- {}; {}", quoted.join(" "), script),
+            Silence::Fails(MARKER_TIMEOUT),
+        )?;
+        if output.exit_status != 0 {
+            return Err(output.stdout.trim().to_string().into());
+        }
+        Ok(output.stdout)
+    }
+
+    /// Rewrites `path` through `printf`, which is safe for any byte over a tty
+    fn write_file(&mut self, path: &Path, data: &[u8]) -> BackendResult<()> {
+        self.run_script(": > \"$1\"", &[path])?;
+        for chunk in data.chunks(WRITE_CHUNK) {
+            let escaped: String = chunk
+                .iter()
+                .map(|b| match b {
+                    b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'_' | b'-' | b' ' => {
+                        (*b as char).to_string()
+                    }
+                    _ => format!("\\{:03o}", b),
+                })
+                .collect();
+            self.run_script(&format!("printf '{}' >> \"$1\"", escaped), &[path])?;
+        }
+        Ok(())
+    }
+}
+
+/// Buffers a whole file and writes it through the shell when finished
+struct TerminalWriter<'a, S: Read + Write> {
+    client: &'a mut TerminalClient<S>,
+    path: PathBuf,
+    data: Vec<u8>,
+}
+
+impl<S: Read + Write> Write for TerminalWriter<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> RemoteWrite for TerminalWriter<'_, S> {
+    fn finish(self: Box<Self>) -> BackendResult<()> {
+        self.client.write_file(&self.path, &self.data)
+    }
+}
+
+/// Splits `marker` with empty quotes, so an echoed command line never matches it
+fn unechoable(marker: &str) -> String {
+    marker.replacen('_', "_''", 1)
+}
+
+/// Last few hundred bytes of `text`, enough to hold a prompt or marker
+fn tail(text: &str) -> &str {
+    let mut start = text.len().saturating_sub(256);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+fn last_line(text: &str) -> &str {
+    text.rsplit('\n').next().unwrap_or_default()
+}
+
+fn ends_with_prompt(tail: &str) -> bool {
+    let line = last_line(tail).trim_end();
+    // Serial consoles outlive the session, so the shell may still carry our prompt
+    !line.is_empty() && (line.ends_with([':', '#', '>', '$', '%']) || line.ends_with(PS1))
+}
+
+fn is_login_prompt(line: &str) -> bool {
+    let line = line.trim_end().to_ascii_lowercase();
+    ["login:", "username:", "user name:", "user:"]
+        .iter()
+        .any(|p| line.ends_with(p))
+}
+
+fn is_password_prompt(line: &str) -> bool {
+    let line = line.trim_end().to_ascii_lowercase();
+    line.ends_with("password:") || line.ends_with("passcode:")
+}
+
+/// Leading part of a prompt that survives mode changes (`switch#` -> `switch(config)#`)
+fn prompt_stem(prompt: &str) -> &str {
+    let end = prompt
+        .find(['#', '>', '$', '%', '(', ':', '[', ' '])
+        .unwrap_or(prompt.len());
+    &prompt[..end]
+}
+
+impl<S: Read + Write> RemoteBackend for TerminalClient<S> {
+    fn label(&self) -> String {
+        self.name.clone()
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn can_exec(&self) -> bool {
+        self.posix
+    }
+
+    fn can_stream(&self) -> bool {
+        false
+    }
+
+    fn passthrough(&self) -> bool {
+        !self.posix
+    }
+
+    fn exec_stream(
+        &mut self,
+        cmd: &str,
+        stdin: Option<&mut dyn Read>,
+        stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        if stdin.is_some() {
+            return Err("Streaming input is not supported over a terminal!".into());
+        }
+
+        if self.posix {
+            let output = self.run_posix(cmd, Silence::Waits)?;
+            stdout.write_all(output.stdout.as_bytes())?;
+            Ok((output.stderr, output.exit_status))
+        } else {
+            let output = self.run_raw(cmd)?;
+            stdout.write_all(output.as_bytes())?;
+            Ok((String::new(), 0))
+        }
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        let output = self.run_script(shell::STAT_SCRIPT, &[path])?;
+        shell::parse_stat(&output)
+            .ok_or_else(|| format!("Unexpected stat output: {}", output).into())
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        let output = self.run_script(shell::READ_DIR_SCRIPT, &[path])?;
+        Ok(shell::parse_read_dir(&output))
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        // Hex dump so binary content survives the terminal
+        let output = self.run_script("od -An -v -tx1 -- \"$1\"", &[path])?;
+        let data = output
+            .split_whitespace()
+            .map(|byte| u8::from_str_radix(byte, 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    fn open_write(&mut self, path: &Path, size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        if !self.posix {
+            return Err(NEEDS_POSIX.into());
+        }
+        Ok(Box::new(TerminalWriter {
+            client: self,
+            path: path.to_path_buf(),
+            data: Vec::with_capacity(size as usize),
+        }))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        self.run_script(shell::RENAME_SCRIPT, &[from, to])?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        self.run_script(shell::REMOVE_SCRIPT, &[path])?;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        self.run_script(shell::MKDIR_SCRIPT, &[path])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,431 @@
+use crate::backend::{
+    BackendResult, DirEntry, FileStat, RemoteBackend, RemoteWrite, normalize_path,
+};
+use crate::config;
+use crate::credential_store;
+use crate::http::PutWriter;
+use base64::{Engine as _, engine::general_purpose};
+use chrono::DateTime;
+use colored::*;
+use percent_encoding::percent_decode_str;
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use ureq::{Agent, AgentBuilder, Request, Response};
+use url::Url;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/><getlastmodified/></prop></propfind>"#;
+
+/// WebDAV share, with paths being the server-side URL paths (`/dav/dir/file`)
+pub struct WebDavClient {
+    agent: Agent,
+    /// `scheme://host:port` of the share, paths are appended to it
+    origin: Url,
+    /// `Authorization` header value, once credentials are known
+    auth: Option<String>,
+    pub host_name: String,
+    pub user: String,
+    pub port: u16,
+    pub current_directory: PathBuf,
+    pub home_directory: PathBuf,
+}
+
+/// Connects to the share at `url`, a user in the URL (`https://user@host/path`) enables
+/// Basic auth with the saved password for `user@host:port`
+pub fn webdav_connect(url: &str, insecure: bool) -> Result<WebDavClient, Box<dyn Error>> {
+    let url = Url::parse(url)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Unsupported scheme {}, use http or https!", url.scheme()).into());
+    }
+    let host_name = url.host_str().ok_or("Missing host in url!")?.to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let user = percent_decode_str(url.username())
+        .decode_utf8()?
+        .to_string();
+
+    let mut agent = AgentBuilder::new().redirects(0);
+    if insecure {
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()?;
+        agent = agent.tls_connector(Arc::new(connector));
+    }
+
+    let mut origin = url.clone();
+    origin.set_path("/");
+    origin.set_query(None);
+    origin.set_fragment(None);
+    origin.set_username("").ok();
+    origin.set_password(None).ok();
+
+    let home = PathBuf::from(percent_decode_str(url.path()).decode_utf8()?.to_string());
+    let mut client = WebDavClient {
+        agent: agent.build(),
+        origin,
+        auth: None,
+        host_name,
+        user,
+        port,
+        current_directory: home.clone(),
+        home_directory: home.clone(),
+    };
+
+    let config = config::load_config().unwrap_or_else(|e| {
+        eprintln!("{} {}.", "Warning: Could not load config:".yellow(), e);
+        config::Config::default()
+    });
+
+    println!("{} {}.", "Connecting to".cyan(), client.origin_label());
+    let mut authenticated = false;
+    if let Some(auth_data) = config.targets.get(&client.config_key())
+        && let config::AuthType::Password = auth_data.auth_type
+    {
+        println!("{}", "Found saved password. Attempting auto-login.".cyan());
+        match credential_store::load_secret(&auth_data.secret) {
+            Ok(password) => {
+                client.set_credentials(&password);
+                match client.stat(&home) {
+                    Ok(_) => {
+                        println!("{}", "✔ Auto-login successful.".green().bold());
+                        authenticated = true;
+                    }
+                    Err(e) if is_unauthorized(e.as_ref()) => {
+                        println!("{}", "✖ Saved password failed!".red())
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Err(e) => eprintln!("{} {}!", "Failed to decrypt saved password:".red(), e),
+        }
+    }
+
+    if !authenticated {
+        client.auth = None;
+        match client.stat(&home) {
+            Ok(_) => {}
+            Err(e) if is_unauthorized(e.as_ref()) => {
+                println!("{}", "Server requires authentication.".yellow());
+                if client.user.is_empty() {
+                    print!("Username: ");
+                    io::stdout().flush()?;
+                    io::stdin().read_line(&mut client.user)?;
+                    client.user = client.user.trim().to_string();
+                }
+                let password = rpassword::prompt_password("Password: ")?;
+                client.set_credentials(&password);
+                if let Err(e) = client.stat(&home) {
+                    println!("Password auth failed: {}.", e);
+                    return Err(format!(
+                        "{}",
+                        "Authentication failed. Please check your credentials!"
+                            .red()
+                            .bold()
+                    )
+                    .into());
+                }
+                println!("{}", "✔ Authenticated.".green().bold());
+
+                let config_key = client.config_key();
+                if !config.targets.contains_key(&config_key) {
+                    config::offer_to_save_password(config, config_key, &password)?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if !client.stat(&home)?.is_dir {
+        return Err(format!("{} is not a collection!", home.display()).into());
+    }
+    println!("{}", "✔ Connected.".green().bold());
+
+    Ok(client)
+}
+
+impl WebDavClient {
+    fn origin_label(&self) -> String {
+        format!(
+            "{}://{}:{}",
+            self.origin.scheme(),
+            self.host_name,
+            self.port
+        )
+    }
+
+    fn config_key(&self) -> String {
+        format!("{}@{}:{}", self.user, self.host_name, self.port)
+    }
+
+    fn set_credentials(&mut self, password: &str) {
+        let token = general_purpose::STANDARD.encode(format!("{}:{}", self.user, password));
+        self.auth = Some(format!("Basic {}", token));
+    }
+
+    /// Absolute URL of `path`, collections get the trailing slash servers expect
+    fn url(&self, path: &Path, collection: bool) -> Url {
+        let mut url = self.origin.clone();
+        let mut path = normalize_path(path).to_string_lossy().to_string();
+        if collection && !path.ends_with('/') {
+            path.push('/');
+        }
+        url.set_path(&path);
+        url
+    }
+
+    fn request(&self, method: &str, url: &Url) -> Request {
+        let request = self.agent.request_url(method, url);
+        match &self.auth {
+            Some(auth) => request.set("Authorization", auth),
+            None => request,
+        }
+    }
+
+    /// Runs a PROPFIND, retrying with a trailing slash if the server redirects to it
+    fn propfind(&self, path: &Path, depth: &str) -> BackendResult<String> {
+        for collection in [depth != "0", true] {
+            let response = self
+                .request("PROPFIND", &self.url(path, collection))
+                .set("Depth", depth)
+                .set("Content-Type", "application/xml; charset=utf-8")
+                .send_string(PROPFIND_BODY)
+                .map_err(status_error)?;
+            match response.status() {
+                207 => return Ok(response.into_string()?),
+                301 | 302 | 307 | 308 if !collection => continue,
+                status => return Err(format!("Unexpected reply {}", status).into()),
+            }
+        }
+        Err(format!("{} keeps redirecting!", path.display()).into())
+    }
+}
+
+fn is_unauthorized(error: &dyn Error) -> bool {
+    error.to_string().starts_with("401 ")
+}
+
+/// Flattens HTTP errors to `<status> <reason>`, releasing the connection
+fn status_error(error: ureq::Error) -> Box<dyn Error> {
+    match error {
+        ureq::Error::Status(status, response) => {
+            format!("{} {}", status, response.status_text()).into()
+        }
+        ureq::Error::Transport(transport) => transport.to_string().into(),
+    }
+}
+
+/// Parses a PROPFIND multistatus body into its entries
+fn parse_multistatus(body: &str) -> BackendResult<Vec<DirEntry>> {
+    let document = roxmltree::Document::parse(body)?;
+    let dav = |node: &roxmltree::Node, name: &str| {
+        node.is_element()
+            && node.tag_name().name() == name
+            && node.tag_name().namespace() == Some("DAV:")
+    };
+
+    let mut entries = Vec::new();
+    for response in document.descendants().filter(|n| dav(n, "response")) {
+        let Some(href) = response
+            .children()
+            .find(|n| dav(n, "href"))
+            .and_then(|n| n.text())
+        else {
+            continue;
+        };
+        // Servers may answer with either absolute paths or full URLs
+        let href = match Url::parse(href.trim()) {
+            Ok(url) => url.path().to_string(),
+            Err(_) => href.trim().to_string(),
+        };
+        let path = percent_decode_str(&href).decode_utf8()?;
+
+        let mut stat = FileStat::default();
+        for propstat in response.children().filter(|n| dav(n, "propstat")) {
+            let ok = propstat
+                .children()
+                .find(|n| dav(n, "status"))
+                .and_then(|n| n.text())
+                .is_some_and(|s| s.contains(" 200 "));
+            let Some(prop) = propstat.children().find(|n| dav(n, "prop")).filter(|_| ok) else {
+                continue;
+            };
+            for property in prop.children().filter(|n| n.is_element()) {
+                let text = property.text().unwrap_or_default().trim();
+                if dav(&property, "resourcetype") {
+                    stat.is_dir = property.children().any(|n| dav(&n, "collection"));
+                } else if dav(&property, "getcontentlength") {
+                    stat.size = text.parse().ok();
+                } else if dav(&property, "getlastmodified") {
+                    stat.mtime = DateTime::parse_from_rfc2822(text)
+                        .ok()
+                        .and_then(|t| u64::try_from(t.timestamp()).ok());
+                }
+            }
+        }
+
+        entries.push(DirEntry {
+            path: normalize_path(Path::new(path.as_ref())),
+            stat,
+        });
+    }
+    Ok(entries)
+}
+
+impl RemoteBackend for WebDavClient {
+    fn label(&self) -> String {
+        format!("webdav:{}@{}:{}", self.user, self.host_name, self.port)
+    }
+
+    fn cwd(&self) -> &Path {
+        &self.current_directory
+    }
+
+    fn set_cwd(&mut self, path: PathBuf) {
+        self.current_directory = path;
+    }
+
+    fn can_exec(&self) -> bool {
+        false
+    }
+
+    fn realpath(&mut self, path: &Path) -> BackendResult<PathBuf> {
+        let expanded = match path.strip_prefix("~") {
+            Ok(rest) => self.home_directory.join(rest),
+            Err(_) => path.to_path_buf(),
+        };
+        Ok(normalize_path(&expanded))
+    }
+
+    fn exec_stream(
+        &mut self,
+        _cmd: &str,
+        _stdin: Option<&mut dyn Read>,
+        _stdout: &mut dyn Write,
+    ) -> BackendResult<(String, i32)> {
+        Err("Shell commands are not available over webdav!".into())
+    }
+
+    fn stat(&mut self, path: &Path) -> BackendResult<FileStat> {
+        let body = self.propfind(path, "0")?;
+        parse_multistatus(&body)?
+            .into_iter()
+            .next()
+            .map(|entry| entry.stat)
+            .ok_or_else(|| format!("No properties returned for {}", path.display()).into())
+    }
+
+    fn read_dir(&mut self, path: &Path) -> BackendResult<Vec<DirEntry>> {
+        let body = self.propfind(path, "1")?;
+        let path = normalize_path(path);
+        Ok(parse_multistatus(&body)?
+            .into_iter()
+            .filter(|entry| entry.path != path)
+            .collect())
+    }
+
+    fn open_read(&mut self, path: &Path) -> BackendResult<Box<dyn Read + '_>> {
+        let response: Response = self
+            .request("GET", &self.url(path, false))
+            .call()
+            .map_err(status_error)?;
+        if response.status() != 200 {
+            return Err(format!("Unexpected reply {}", response.status()).into());
+        }
+        Ok(Box::new(response.into_reader()))
+    }
+
+    fn open_write(&mut self, path: &Path, size: u64) -> BackendResult<Box<dyn RemoteWrite + '_>> {
+        let request = self
+            .request("PUT", &self.url(path, false))
+            .set("Content-Length", &size.to_string());
+        Ok(Box::new(PutWriter::start(request, status_error)?))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> BackendResult<()> {
+        let collection = self.stat(from)?.is_dir;
+        self.request("MOVE", &self.url(from, collection))
+            .set("Destination", self.url(to, collection).as_str())
+            .set("Overwrite", "F")
+            .call()
+            .map_err(status_error)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> BackendResult<()> {
+        // DELETE on a collection is recursive, keep `rm` as cautious as rmdir
+        let collection = self.stat(path)?.is_dir;
+        if collection && !self.read_dir(path)?.is_empty() {
+            return Err(format!("{} is not empty!", path.display()).into());
+        }
+        self.request("DELETE", &self.url(path, collection))
+            .call()
+            .map_err(status_error)?;
+        Ok(())
+    }
+
+    fn mkdir(&mut self, path: &Path) -> BackendResult<()> {
+        self.request("MKCOL", &self.url(path, true))
+            .call()
+            .map_err(status_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTISTATUS: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/dav/docs/</d:href>
+    <d:propstat>
+      <d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://files.example.com/dav/docs/my%20notes.txt</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getcontentlength>42</d:getcontentlength>
+        <d:getlastmodified>Tue, 02 Jan 2024 03:04:05 GMT</d:getlastmodified>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop><d:getcontentlength>999</d:getcontentlength></d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parse_multistatus_reads_entries() {
+        let entries = parse_multistatus(MULTISTATUS).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].path, Path::new("/dav/docs"));
+        assert!(entries[0].stat.is_dir);
+
+        assert_eq!(entries[1].path, Path::new("/dav/docs/my notes.txt"));
+        assert!(!entries[1].stat.is_dir);
+        assert_eq!(entries[1].stat.size, Some(42));
+        assert_eq!(entries[1].stat.mtime, Some(1704164645));
+    }
+
+    #[test]
+    fn parse_multistatus_rejects_bad_xml() {
+        assert!(parse_multistatus("<multistatus").is_err());
+        assert!(
+            parse_multistatus("<x:multistatus xmlns:x=\"other:\"/>")
+                .unwrap()
+                .is_empty()
+        );
+    }
+}